@@ -0,0 +1,868 @@
+// src/alerts.rs
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Datelike, Local, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep, Duration};
+use crate::anomaly::{self, BaselineKind};
+use crate::config_manager::{parse_duration, AlertRuleConfig, Config, MaintenanceWindow};
+use crate::history::{HistoryStore, SharedHistory};
+use crate::remote::RemoteStatus;
+use crate::system_info::SystemData;
+
+/// How many resolved alerts are kept for /api/alerts.
+const RECENT_ALERTS_KEPT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    fn parse(text: &str) -> Option<Op> {
+        match text {
+            ">" => Some(Op::Gt),
+            ">=" => Some(Op::Ge),
+            "<" => Some(Op::Lt),
+            "<=" => Some(Op::Le),
+            "==" | "=" => Some(Op::Eq),
+            "!=" => Some(Op::Ne),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+        }
+    }
+
+    fn holds(&self, left: f64, right: f64) -> bool {
+        match self {
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    // "<metric path> <op> <number>", e.g. "polled.cpu_usage > 90".
+    Threshold { path: String, op: Op, threshold: f64 },
+    // "service <name> down" or "service <name> flapping".
+    Service { service: String, flapping: bool },
+    // "remote offline" (any remote), "remote <name or ip:port> offline",
+    // or the same with "flapping".
+    Remote { remote: Option<String>, flapping: bool },
+    // "anomaly <series> [above|below] <N> sigma [baseline ewma|weekly]".
+    Anomaly { series: String, direction: Direction, sigma: f64, baseline: BaselineKind },
+    // "forecast <disk|inodes|swap|disk:/mount> full within <duration>".
+    Forecast { resource: String, within_secs: i64 },
+}
+
+/// Which deviations from the baseline an anomaly rule reacts to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Both,
+    Above,
+    Below,
+}
+
+fn parse_anomaly(series: &str, rest: &[&str]) -> Result<Condition, String> {
+    let (direction, rest) = match rest {
+        ["above", rest @ ..] => (Direction::Above, rest),
+        ["below", rest @ ..] => (Direction::Below, rest),
+        _ => (Direction::Both, rest),
+    };
+    // The deviation may be written "3 sigma", "3sigma" or "3σ".
+    let (sigma, rest) = match rest {
+        [number, "sigma", rest @ ..] => (*number, rest),
+        [number, rest @ ..] if number.ends_with("sigma") || number.ends_with('σ') =>
+            (number.trim_end_matches("sigma").trim_end_matches('σ'), rest),
+        _ => return Err("expected '<N> sigma'".into()),
+    };
+    let sigma: f64 = sigma.parse().map_err(|_| format!("invalid number '{}'", sigma))?;
+    let baseline = match rest {
+        [] => BaselineKind::Ewma,
+        ["baseline", name] => BaselineKind::from_name(name).ok_or_else(|| format!("unknown baseline '{}'", name))?,
+        _ => return Err(format!("unexpected '{}'", rest.join(" "))),
+    };
+    Ok(Condition::Anomaly { series: series.to_string(), direction, sigma, baseline })
+}
+
+/// A parsed `[[alerts]]` entry.
+#[derive(Debug, Clone)]
+struct Rule {
+    name: String,
+    condition: Condition,
+    // How long the condition must hold before the alert fires.
+    for_secs: i64,
+    severity: String,
+    description: Option<String>,
+    labels: HashMap<String, String>,
+}
+
+/// Parses a rule such as "polled.cpu_usage > 90 for 5m".
+fn parse_rule(config: &AlertRuleConfig) -> Result<Rule, String> {
+    let mut tokens: Vec<&str> = config.rule.split_whitespace().collect();
+    let mut for_secs = 0;
+    if tokens.len() >= 2 && tokens[tokens.len() - 2] == "for" {
+        let duration = tokens[tokens.len() - 1];
+        for_secs = parse_duration(duration).ok_or_else(|| format!("invalid duration '{}'", duration))?;
+        tokens.truncate(tokens.len() - 2);
+    }
+
+    let condition = match tokens.as_slice() {
+        ["service", service, state @ ("down" | "flapping")] =>
+            Condition::Service { service: service.to_string(), flapping: *state == "flapping" },
+        ["remote", state @ ("offline" | "flapping")] => Condition::Remote { remote: None, flapping: *state == "flapping" },
+        ["remote", remote, state @ ("offline" | "flapping")] =>
+            Condition::Remote { remote: Some(remote.to_string()), flapping: *state == "flapping" },
+        ["anomaly", series, rest @ ..] => parse_anomaly(series, rest)?,
+        ["forecast", resource, "full", "within", duration] => Condition::Forecast {
+            resource: resource.to_string(),
+            within_secs: parse_duration(duration).ok_or_else(|| format!("invalid duration '{}'", duration))?,
+        },
+        [path, op, threshold] => Condition::Threshold {
+            path: path.to_string(),
+            op: Op::parse(op).ok_or_else(|| format!("unknown operator '{}'", op))?,
+            threshold: threshold.parse().map_err(|_| format!("invalid number '{}'", threshold))?,
+        },
+        _ => return Err(format!("cannot parse rule '{}'", config.rule)),
+    };
+
+    Ok(Rule {
+        name: config.name.clone(),
+        condition,
+        for_secs,
+        severity: config.severity.clone().unwrap_or_else(|| "warning".into()),
+        description: config.description.clone(),
+        labels: config.labels.clone().unwrap_or_default(),
+    })
+}
+
+/// Looks up a dotted metric path ("polled.cpu_usage") in the local metrics.
+/// Paths not found at the top level are tried under "polled", so
+/// "disk_usage_percent" works as a shorthand.
+fn resolve_path<'a>(metrics: &'a Value, path: &str) -> Option<&'a Value> {
+    let pointer = format!("/{}", path.replace('.', "/"));
+    metrics.pointer(&pointer).or_else(|| metrics.get("polled").and_then(|p| p.pointer(&pointer)))
+}
+
+/// The current value of a rule for one instance (a service, a remote, ...).
+struct Observation {
+    instance: String,
+    // The remote pylon the observation is about (None for this pylon).
+    pylon: Option<String>,
+    active: bool,
+    value: Option<f64>,
+    message: String,
+}
+
+fn observe(rule: &Rule, metrics: &Value, remotes: &HashMap<String, RemoteStatus>, history: &HistoryStore) -> Vec<Observation> {
+    match &rule.condition {
+        Condition::Threshold { path, op, threshold } => {
+            let value = resolve_path(metrics, path).and_then(|v| v.as_f64().or_else(|| v.as_bool().map(|b| b as u8 as f64)));
+            match value {
+                Some(value) => vec![Observation {
+                    instance: String::new(),
+                    pylon: None,
+                    active: op.holds(value, *threshold),
+                    value: Some(value),
+                    message: format!("{} is {} ({} {})", path, value, op.symbol(), threshold),
+                }],
+                None => Vec::new(),
+            }
+        },
+        Condition::Service { service, flapping } => {
+            let status = metrics.pointer("/polled/services")
+                .and_then(|s| s.as_array())
+                .and_then(|services| services.iter().find(|s| s["name"].as_str() == Some(service.as_str())));
+            match status {
+                Some(status) => {
+                    let running = status["running"].as_bool().unwrap_or(false);
+                    let (active, message) = if *flapping {
+                        let transitions = status["transitions"].as_u64().unwrap_or(0);
+                        let is_flapping = status["flapping"].as_bool().unwrap_or(false);
+                        (is_flapping, format!("service {} is {} ({} state changes)", service,
+                            if is_flapping { "flapping" } else { "stable" }, transitions))
+                    } else {
+                        (!running, format!("service {} is {}", service, if running { "running" } else { "down" }))
+                    };
+                    vec![Observation { instance: service.clone(), pylon: None, active, value: None, message }]
+                },
+                None => Vec::new(),
+            }
+        },
+        Condition::Remote { remote, flapping } => remotes.iter()
+            // Removed remotes resolve their alerts instead of going on firing.
+            .filter(|(_, status)| status.removed_at.is_none())
+            .filter(|(key, status)| match remote {
+                Some(wanted) => *key == wanted
+                    || format!("{}:{}", status.ip, status.port) == *wanted
+                    || status.name.as_deref() == Some(wanted.as_str()),
+                None => true,
+            })
+            .map(|(key, status)| {
+                let label = status.name.clone().unwrap_or_else(|| key.clone());
+                let (active, message) = if *flapping {
+                    (status.flapping, format!("remote {} is {} ({} state changes)", label,
+                        if status.flapping { "flapping" } else { "stable" }, status.transitions))
+                } else {
+                    let reason = status.last_error.as_ref().filter(|_| !status.online)
+                        .map(|e| format!(" ({})", e.message)).unwrap_or_default();
+                    (!status.online, format!("remote {} is {}{}", label, if status.online { "online" } else { "offline" }, reason))
+                };
+                Observation {
+                    instance: key.clone(),
+                    active,
+                    value: None,
+                    message,
+                    pylon: Some(label),
+                }
+            })
+            .collect(),
+        Condition::Forecast { resource, within_secs } => metrics["forecasts"].as_array().into_iter().flatten()
+            .filter(|f| f["key"].as_str() == Some(resource.as_str()) || f["kind"].as_str() == Some(resource.as_str()))
+            .map(|f| {
+                let key = f["key"].as_str().unwrap_or_default().to_string();
+                let hours = f["hours_until_full"].as_f64();
+                Observation {
+                    active: hours.is_some_and(|hours| hours * 3600.0 <= *within_secs as f64),
+                    value: hours,
+                    message: match hours {
+                        Some(hours) => format!("{} is forecast to be full in {:.1}h", key, hours),
+                        None => format!("{} is not filling up", key),
+                    },
+                    instance: key,
+                    pylon: None,
+                }
+            })
+            .collect(),
+        Condition::Anomaly { series, direction, sigma, baseline } => {
+            let now = Utc::now().timestamp();
+            // Only judge fresh samples against a baseline with enough history.
+            let sample = match history.latest_sample(series) {
+                Some(sample) if now - sample.ts <= 120 => sample,
+                _ => return Vec::new(),
+            };
+            let expected = match anomaly::baseline(history, series, *baseline, now) {
+                Some(expected) => expected,
+                None => return Vec::new(),
+            };
+            let z = expected.z_score(sample.value);
+            let active = match direction {
+                Direction::Both => z.abs() > *sigma,
+                Direction::Above => z > *sigma,
+                Direction::Below => -z > *sigma,
+            };
+            vec![Observation {
+                instance: String::new(),
+                pylon: None,
+                active,
+                value: Some(sample.value),
+                message: format!(
+                    "{} is {:.2}, {:.1}σ {} the {} baseline {:.2} ± {:.2}",
+                    series, sample.value, z.abs(), if z >= 0.0 { "above" } else { "below" },
+                    baseline.name(), expected.mean, expected.std_dev
+                ),
+            }]
+        },
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Pending,
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Alert {
+    // "<rule>" or "<rule>:<instance>".
+    pub id: String,
+    pub rule: String,
+    pub instance: String,
+    // The pylon the alert is about: this one, or a remote for "remote offline".
+    pub pylon: String,
+    pub severity: String,
+    // Rule labels plus "severity" and "instance".
+    pub labels: HashMap<String, String>,
+    pub state: AlertState,
+    pub value: Option<f64>,
+    pub message: String,
+    pub description: Option<String>,
+    pub pending_since: DateTime<Utc>,
+    pub fired_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub ack_comment: Option<String>,
+    // Silence id or "maintenance:<name>" while notifications are suppressed.
+    pub silenced_by: Option<String>,
+    // Whether the firing notification went out (so resolving is announced too).
+    #[serde(skip)]
+    pub notified: bool,
+}
+
+/// Suppresses notifications for matching alerts until `until`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Silence {
+    pub id: String,
+    pub pylon: Option<String>,
+    pub rule: Option<String>,
+    pub labels: Option<HashMap<String, String>>,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+}
+
+/// True when every given matcher equals the alert's pylon, rule and labels.
+fn matches(pylon: &Option<String>, rule: &Option<String>, labels: &Option<HashMap<String, String>>, alert: &Alert) -> bool {
+    pylon.as_ref().is_none_or(|pylon| *pylon == alert.pylon)
+        && rule.as_ref().is_none_or(|rule| *rule == alert.rule)
+        && labels.iter().flatten().all(|(key, value)| alert.labels.get(key) == Some(value))
+}
+
+/// Whether a recurring maintenance window is open at `now`. Windows may run
+/// past midnight, so openings on earlier days are checked too.
+fn in_window(window: &MaintenanceWindow, now: DateTime<Local>) -> Result<bool, String> {
+    let start = NaiveTime::parse_from_str(&window.start, "%H:%M").map_err(|_| format!("invalid start '{}'", window.start))?;
+    let length = parse_duration(&window.duration).ok_or_else(|| format!("invalid duration '{}'", window.duration))?;
+    let mut days = Vec::new();
+    for day in window.days.iter().flatten() {
+        days.push(day.parse::<Weekday>().map_err(|_| format!("invalid day '{}'", day))?);
+    }
+    // A window of a week or more is open whenever it opened in the last week.
+    Ok((0..=(length / 86400 + 1).min(8)).any(|back| {
+        let date = now.date_naive() - chrono::Duration::days(back);
+        if !days.is_empty() && !days.contains(&date.weekday()) {
+            return false;
+        }
+        match date.and_time(start).and_local_timezone(Local).earliest() {
+            Some(opened) => now >= opened && (now - opened).num_seconds() < length,
+            None => false,
+        }
+    }))
+}
+
+const SILENCES_FILE: &str = "silences.json";
+
+fn load_silences() -> Vec<Silence> {
+    match std::fs::read_to_string(SILENCES_FILE) {
+        Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+            println!("Failed to parse {}: {}", SILENCES_FILE, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Active (pending or firing) alerts plus recently resolved ones.
+#[derive(Default)]
+pub struct AlertManager {
+    pub active: HashMap<String, Alert>,
+    pub recent: VecDeque<Alert>,
+    // Rules and maintenance windows that failed to parse, with the reason.
+    pub errors: HashMap<String, String>,
+    // Silences that have not expired yet, persisted to silences.json.
+    pub silences: Vec<Silence>,
+    // Names of the maintenance windows currently open.
+    pub maintenance: Vec<String>,
+    // Receives every alert that fires or resolves (see notifiers.rs).
+    pub notify: Option<mpsc::UnboundedSender<Alert>>,
+}
+
+pub type SharedAlerts = Arc<Mutex<AlertManager>>;
+
+impl AlertManager {
+    pub fn new(notify: mpsc::UnboundedSender<Alert>) -> Self {
+        Self { notify: Some(notify), silences: load_silences(), ..Default::default() }
+    }
+
+    fn announce(&self, alert: &Alert) {
+        if let Some(notify) = &self.notify {
+            let _ = notify.send(alert.clone());
+        }
+    }
+
+    fn save_silences(&self) {
+        let result = serde_json::to_string_pretty(&self.silences)
+            .map_err(std::io::Error::other)
+            .and_then(|text| std::fs::write(SILENCES_FILE, text));
+        if let Err(e) = result {
+            println!("Failed to save {}: {}", SILENCES_FILE, e);
+        }
+    }
+
+    /// Marks an active alert as acknowledged; no notification is sent for it
+    /// until it resolves.
+    pub fn acknowledge(&mut self, id: &str, comment: Option<String>) -> Option<Alert> {
+        let alert = self.active.get_mut(id)?;
+        alert.acknowledged_at = Some(Utc::now());
+        alert.ack_comment = comment;
+        Some(alert.clone())
+    }
+
+    pub fn add_silence(
+        &mut self,
+        pylon: Option<String>,
+        rule: Option<String>,
+        labels: Option<HashMap<String, String>>,
+        duration_secs: i64,
+        comment: Option<String>
+    ) -> Option<Silence> {
+        let now = Utc::now();
+        // None when the end time does not fit in a timestamp.
+        let until = now.checked_add_signed(chrono::Duration::try_seconds(duration_secs)?)?;
+        let silence = Silence {
+            id: format!("{:x}", now.timestamp_nanos_opt().unwrap_or_default()),
+            pylon,
+            rule,
+            labels,
+            comment,
+            created_at: now,
+            until,
+        };
+        self.silences.push(silence.clone());
+        self.save_silences();
+        Some(silence)
+    }
+
+    pub fn remove_silence(&mut self, id: &str) -> bool {
+        let before = self.silences.len();
+        self.silences.retain(|silence| silence.id != id);
+        let removed = self.silences.len() != before;
+        if removed {
+            self.save_silences();
+        }
+        removed
+    }
+
+    /// Moves a firing alert to the resolved list.
+    fn resolve(&mut self, id: &str, now: DateTime<Utc>) {
+        if let Some(mut alert) = self.active.remove(id) {
+            if alert.state == AlertState::Firing {
+                alert.state = AlertState::Resolved;
+                alert.resolved_at = Some(now);
+                println!("Alert resolved: {} ({})", alert.id, alert.message);
+                if alert.notified {
+                    self.announce(&alert);
+                }
+                self.recent.push_front(alert);
+                self.recent.truncate(RECENT_ALERTS_KEPT);
+            }
+        }
+    }
+
+    /// Applies silences and maintenance windows to the active alerts and
+    /// sends the firing notification of alerts that are not suppressed. An
+    /// alert that fired while silenced is announced once the silence ends.
+    fn apply_suppression(&mut self, windows: &[MaintenanceWindow], now: DateTime<Utc>) {
+        let before = self.silences.len();
+        self.silences.retain(|silence| silence.until > now);
+        if self.silences.len() != before {
+            self.save_silences();
+        }
+
+        let mut open = Vec::new();
+        for window in windows {
+            match in_window(window, now.with_timezone(&Local)) {
+                Ok(true) => open.push(window),
+                Ok(false) => {},
+                Err(e) => {
+                    self.errors.insert(format!("maintenance:{}", window.name), e);
+                }
+            }
+        }
+        self.maintenance = open.iter().map(|window| window.name.clone()).collect();
+
+        let mut announce = Vec::new();
+        for alert in self.active.values_mut() {
+            alert.silenced_by = self.silences.iter()
+                .find(|s| matches(&s.pylon, &s.rule, &s.labels, alert))
+                .map(|s| s.id.clone())
+                .or_else(|| open.iter()
+                    .find(|w| matches(&w.pylon, &w.rule, &w.labels, alert))
+                    .map(|w| format!("maintenance:{}", w.name)));
+            if alert.state == AlertState::Firing && !alert.notified
+                && alert.silenced_by.is_none() && alert.acknowledged_at.is_none() {
+                alert.notified = true;
+                announce.push(alert.clone());
+            }
+        }
+        for alert in announce {
+            self.announce(&alert);
+        }
+    }
+
+    /// Runs every rule against the current metrics and advances the
+    /// pending -> firing -> resolved state machine of each alert.
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate(
+        &mut self,
+        rules: &[AlertRuleConfig],
+        windows: &[MaintenanceWindow],
+        local_pylon: &str,
+        metrics: &Value,
+        remotes: &HashMap<String, RemoteStatus>,
+        history: &HistoryStore,
+        now: DateTime<Utc>
+    ) {
+        let mut seen = Vec::new();
+        self.errors.clear();
+
+        for config in rules {
+            let rule = match parse_rule(config) {
+                Ok(rule) => rule,
+                Err(e) => {
+                    self.errors.insert(config.name.clone(), e);
+                    continue;
+                }
+            };
+            for observation in observe(&rule, metrics, remotes, history) {
+                let id = if observation.instance.is_empty() {
+                    rule.name.clone()
+                } else {
+                    format!("{}:{}", rule.name, observation.instance)
+                };
+                seen.push(id.clone());
+                if !observation.active {
+                    if let Some(alert) = self.active.get_mut(&id) {
+                        alert.value = observation.value;
+                        alert.message = observation.message;
+                    }
+                    self.resolve(&id, now);
+                    continue;
+                }
+
+                let alert = self.active.entry(id.clone()).or_insert_with(|| {
+                    let mut labels = rule.labels.clone();
+                    labels.insert("severity".into(), rule.severity.clone());
+                    if !observation.instance.is_empty() {
+                        labels.insert("instance".into(), observation.instance.clone());
+                    }
+                    Alert {
+                        id: id.clone(),
+                        rule: rule.name.clone(),
+                        instance: observation.instance.clone(),
+                        pylon: observation.pylon.clone().unwrap_or_else(|| local_pylon.to_string()),
+                        severity: rule.severity.clone(),
+                        labels,
+                        state: AlertState::Pending,
+                        value: None,
+                        message: String::new(),
+                        description: rule.description.clone(),
+                        pending_since: now,
+                        fired_at: None,
+                        resolved_at: None,
+                        acknowledged_at: None,
+                        ack_comment: None,
+                        silenced_by: None,
+                        notified: false,
+                    }
+                });
+                alert.value = observation.value;
+                alert.message = observation.message;
+                if alert.state == AlertState::Pending && (now - alert.pending_since).num_seconds() >= rule.for_secs {
+                    alert.state = AlertState::Firing;
+                    alert.fired_at = Some(now);
+                    println!("Alert firing: {} ({})", alert.id, alert.message);
+                }
+            }
+        }
+
+        // Alerts whose rule or instance disappeared (e.g. removed from config) are resolved.
+        let gone: Vec<String> = self.active.keys().filter(|id| !seen.contains(id)).cloned().collect();
+        for id in gone {
+            self.resolve(&id, now);
+        }
+
+        self.apply_suppression(windows, now);
+    }
+}
+
+/// Evaluates the configured `[[alerts]]` rules every few seconds.
+pub async fn evaluate_alerts(
+    config_arc: Arc<RwLock<Config>>,
+    system_data: Arc<Mutex<SystemData>>,
+    remote_statuses: Arc<Mutex<HashMap<String, RemoteStatus>>>,
+    history: SharedHistory,
+    alerts: SharedAlerts,
+    mut shutdown: watch::Receiver<bool>
+) {
+    loop {
+        let (rules, windows, local_pylon) = {
+            let config = config_arc.read().unwrap();
+            (
+                config.alerts.clone().unwrap_or_default(),
+                config.maintenance.clone().unwrap_or_default(),
+                config.name.clone().unwrap_or_else(|| "Pylon".into()),
+            )
+        };
+        let metrics = system_data.lock().unwrap().to_json();
+        let remotes = remote_statuses.lock().unwrap().clone();
+        {
+            let history = history.lock().unwrap();
+            alerts.lock().unwrap().evaluate(&rules, &windows, &local_pylon, &metrics, &remotes, &history, Utc::now());
+        }
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down alert evaluator.");
+                    break;
+                }
+            },
+            _ = sleep(Duration::from_secs(5)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(text: &str) -> Result<Rule, String> {
+        parse_rule(&AlertRuleConfig {
+            name: "test".into(),
+            rule: text.into(),
+            severity: None,
+            description: None,
+            labels: None,
+        })
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Some(90));
+        assert_eq!(parse_duration("30s"), Some(30));
+        assert_eq!(parse_duration("5m"), Some(300));
+        assert_eq!(parse_duration("2h"), Some(7200));
+        assert_eq!(parse_duration(" 7d "), Some(604800));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("5w"), None);
+        assert_eq!(parse_duration("-5m"), None);
+        assert_eq!(parse_duration("9223372036854775807d"), None);
+        assert_eq!(parse_duration("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn parses_threshold_rule_with_duration() {
+        let rule = rule("polled.cpu_usage >= 90 for 5m").unwrap();
+        assert_eq!(rule.for_secs, 300);
+        assert_eq!(rule.severity, "warning");
+        match rule.condition {
+            Condition::Threshold { path, op, threshold } => {
+                assert_eq!((path.as_str(), op, threshold), ("polled.cpu_usage", Op::Ge, 90.0));
+            },
+            other => panic!("unexpected condition {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_keyword_rules() {
+        assert!(matches!(rule("service nginx flapping").unwrap().condition,
+            Condition::Service { ref service, flapping: true } if service == "nginx"));
+        assert!(matches!(rule("remote offline for 2m").unwrap().condition,
+            Condition::Remote { remote: None, flapping: false }));
+        assert!(matches!(rule("remote 10.0.0.2:6989 offline").unwrap().condition,
+            Condition::Remote { remote: Some(ref remote), .. } if remote == "10.0.0.2:6989"));
+        assert!(matches!(rule("anomaly cpu above 3σ baseline weekly").unwrap().condition,
+            Condition::Anomaly { direction: Direction::Above, sigma, baseline: BaselineKind::Weekly, .. } if sigma == 3.0));
+        assert!(matches!(rule("anomaly cpu 2.5 sigma").unwrap().condition,
+            Condition::Anomaly { direction: Direction::Both, baseline: BaselineKind::Ewma, .. }));
+        assert!(matches!(rule("forecast disk full within 1d").unwrap().condition,
+            Condition::Forecast { within_secs: 86400, .. }));
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        assert!(rule("").is_err());
+        assert!(rule("cpu_usage").is_err());
+        assert!(rule("cpu_usage => 90").is_err());
+        assert!(rule("cpu_usage > ninety").is_err());
+        assert!(rule("cpu_usage > 90 for ever").is_err());
+        assert!(rule("service nginx sleeping").is_err());
+        assert!(rule("anomaly cpu 3").is_err());
+        assert!(rule("anomaly cpu 3 sigma baseline monthly").is_err());
+        assert!(rule("forecast disk full within soon").is_err());
+    }
+
+    struct Harness {
+        manager: AlertManager,
+        notified: mpsc::UnboundedReceiver<Alert>,
+        rules: Vec<AlertRuleConfig>,
+        windows: Vec<MaintenanceWindow>,
+        history: HistoryStore,
+        start: DateTime<Utc>,
+    }
+
+    impl Harness {
+        fn new(text: &str) -> Self {
+            let (tx, notified) = mpsc::unbounded_channel();
+            Self {
+                // Not AlertManager::new, which would read silences.json.
+                manager: AlertManager { notify: Some(tx), ..Default::default() },
+                notified,
+                rules: vec![AlertRuleConfig {
+                    name: "cpu".into(),
+                    rule: text.into(),
+                    severity: None,
+                    description: None,
+                    labels: None,
+                }],
+                windows: Vec::new(),
+                history: HistoryStore::open(&crate::config_manager::HistoryConfig { enabled: Some(false), ..Default::default() }),
+                start: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            }
+        }
+
+        /// Evaluates with the given CPU usage `secs` after the start.
+        fn step(&mut self, secs: i64, cpu: f64) -> Option<AlertState> {
+            let metrics = serde_json::json!({ "polled": { "cpu_usage": cpu } });
+            let now = self.start + chrono::Duration::seconds(secs);
+            self.manager.evaluate(&self.rules, &self.windows, "local", &metrics, &HashMap::new(), &self.history, now);
+            self.manager.active.get("cpu").map(|alert| alert.state)
+        }
+
+        fn sent(&mut self) -> Vec<AlertState> {
+            std::iter::from_fn(|| self.notified.try_recv().ok()).map(|alert| alert.state).collect()
+        }
+    }
+
+    #[test]
+    fn pending_alert_fires_after_its_duration_and_resolves() {
+        let mut harness = Harness::new("polled.cpu_usage > 90 for 5m");
+        assert_eq!(harness.step(0, 95.0), Some(AlertState::Pending));
+        assert_eq!(harness.step(299, 95.0), Some(AlertState::Pending));
+        assert!(harness.sent().is_empty());
+        assert_eq!(harness.step(300, 95.0), Some(AlertState::Firing));
+        assert_eq!(harness.sent(), vec![AlertState::Firing]);
+        assert_eq!(harness.step(310, 96.0), Some(AlertState::Firing));
+        assert!(harness.sent().is_empty());
+
+        assert_eq!(harness.step(320, 10.0), None);
+        assert_eq!(harness.sent(), vec![AlertState::Resolved]);
+        let resolved = &harness.manager.recent[0];
+        assert_eq!(resolved.state, AlertState::Resolved);
+        assert_eq!(resolved.fired_at, Some(harness.start + chrono::Duration::seconds(300)));
+        assert_eq!(resolved.resolved_at, Some(harness.start + chrono::Duration::seconds(320)));
+    }
+
+    #[test]
+    fn pending_alert_that_clears_is_dropped_silently() {
+        let mut harness = Harness::new("polled.cpu_usage > 90 for 5m");
+        assert_eq!(harness.step(0, 95.0), Some(AlertState::Pending));
+        assert_eq!(harness.step(200, 50.0), None);
+        // The duration starts over once the condition holds again.
+        assert_eq!(harness.step(400, 95.0), Some(AlertState::Pending));
+        assert_eq!(harness.step(600, 95.0), Some(AlertState::Pending));
+        assert!(harness.sent().is_empty());
+        assert!(harness.manager.recent.is_empty());
+
+        let mut instant = Harness::new("polled.cpu_usage > 90");
+        assert_eq!(instant.step(0, 95.0), Some(AlertState::Firing));
+        assert_eq!(instant.sent(), vec![AlertState::Firing]);
+    }
+
+    #[test]
+    fn silence_holds_back_notifications_until_removed() {
+        let mut harness = Harness::new("polled.cpu_usage > 90");
+        harness.manager.silences.push(Silence {
+            id: "s1".into(),
+            pylon: None,
+            rule: Some("cpu".into()),
+            labels: None,
+            comment: None,
+            created_at: harness.start,
+            until: harness.start + chrono::Duration::hours(1),
+        });
+        assert_eq!(harness.step(0, 95.0), Some(AlertState::Firing));
+        assert_eq!(harness.manager.active["cpu"].silenced_by.as_deref(), Some("s1"));
+        assert!(harness.sent().is_empty());
+
+        harness.manager.silences.clear();
+        harness.step(10, 95.0);
+        assert_eq!(harness.manager.active["cpu"].silenced_by, None);
+        assert_eq!(harness.sent(), vec![AlertState::Firing]);
+    }
+
+    #[test]
+    fn rejects_durations_past_the_end_of_time() {
+        let mut manager = AlertManager::default();
+        assert!(manager.add_silence(None, None, None, i64::MAX / 1000, None).is_none());
+        assert!(manager.silences.is_empty());
+
+        let endless = MaintenanceWindow {
+            name: "endless".into(),
+            days: None,
+            start: "00:00".into(),
+            duration: "99999999999d".into(),
+            pylon: None,
+            rule: None,
+            labels: None,
+        };
+        assert_eq!(in_window(&endless, Local::now()), Ok(true));
+    }
+
+    #[test]
+    fn silence_matchers_must_all_match() {
+        let mut harness = Harness::new("polled.cpu_usage > 90");
+        harness.manager.silences.push(Silence {
+            id: "other".into(),
+            pylon: Some("elsewhere".into()),
+            rule: Some("cpu".into()),
+            labels: None,
+            comment: None,
+            created_at: harness.start,
+            until: harness.start + chrono::Duration::hours(1),
+        });
+        harness.step(0, 95.0);
+        assert_eq!(harness.manager.active["cpu"].silenced_by, None);
+        assert_eq!(harness.sent(), vec![AlertState::Firing]);
+    }
+
+    #[test]
+    fn maintenance_window_suppresses_while_open() {
+        let mut harness = Harness::new("polled.cpu_usage > 90");
+        let opened = (harness.start - chrono::Duration::minutes(10)).with_timezone(&Local);
+        harness.windows.push(MaintenanceWindow {
+            name: "deploy".into(),
+            days: None,
+            start: opened.format("%H:%M").to_string(),
+            duration: "30m".into(),
+            pylon: Some("local".into()),
+            rule: None,
+            labels: None,
+        });
+        assert_eq!(harness.step(0, 95.0), Some(AlertState::Firing));
+        assert_eq!(harness.manager.maintenance, vec!["deploy".to_string()]);
+        assert_eq!(harness.manager.active["cpu"].silenced_by.as_deref(), Some("maintenance:deploy"));
+        assert!(harness.sent().is_empty());
+
+        // Well past the window's end the held-back notification goes out.
+        harness.step(3600, 95.0);
+        assert!(harness.manager.maintenance.is_empty());
+        assert_eq!(harness.sent(), vec![AlertState::Firing]);
+    }
+}
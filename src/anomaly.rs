@@ -0,0 +1,170 @@
+// src/anomaly.rs
+
+use crate::history::{HistoryStore, Rollup};
+
+/// Minute rollups needed before a baseline is trusted.
+const MIN_BASELINE_POINTS: usize = 10;
+/// Hour rollups needed when a weekly baseline falls back to the hour tier.
+const MIN_HOURLY_POINTS: usize = 4;
+
+/// How the expected value of a metric is estimated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BaselineKind {
+    // Exponentially weighted mean and deviation of the last few hours.
+    Ewma,
+    // Mean and deviation of the same hour one week earlier.
+    Weekly,
+}
+
+impl BaselineKind {
+    pub fn from_name(name: &str) -> Option<BaselineKind> {
+        match name {
+            "ewma" => Some(BaselineKind::Ewma),
+            "weekly" => Some(BaselineKind::Weekly),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BaselineKind::Ewma => "ewma",
+            BaselineKind::Weekly => "weekly",
+        }
+    }
+}
+
+/// Expected value and spread of a metric.
+#[derive(Debug, Clone, Copy)]
+pub struct Baseline {
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+impl Baseline {
+    /// How many standard deviations `value` is from the mean (signed). The
+    /// deviation is floored so a perfectly flat history does not turn every
+    /// tiny change into an infinite score.
+    pub fn z_score(&self, value: f64) -> f64 {
+        let floor = (self.mean.abs() * 0.01).max(1e-6);
+        (value - self.mean) / self.std_dev.max(floor)
+    }
+}
+
+/// EWMA mean and variance with a span of `span` points (zero for no points).
+fn ewma(values: &[f64], span: f64) -> Baseline {
+    let Some((&first, rest)) = values.split_first() else {
+        return Baseline { mean: 0.0, std_dev: 0.0 };
+    };
+    let alpha = 2.0 / (span + 1.0);
+    let mut mean = first;
+    let mut variance = 0.0;
+    for &value in rest {
+        let diff = value - mean;
+        let increment = alpha * diff;
+        mean += increment;
+        variance = (1.0 - alpha) * (variance + diff * increment);
+    }
+    Baseline { mean, std_dev: variance.sqrt() }
+}
+
+fn mean_and_std(values: &[f64]) -> Baseline {
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    Baseline { mean, std_dev: variance.sqrt() }
+}
+
+/// Computes the baseline of `series` at `now` from its minute rollups. The
+/// current minute is left out so an ongoing spike does not pull the baseline
+/// towards itself. Returns None while there is too little history.
+pub fn baseline(history: &HistoryStore, series: &str, kind: BaselineKind, now: i64) -> Option<Baseline> {
+    let current_minute = now - now.rem_euclid(60);
+    let averages = |rollups: Vec<Rollup>| rollups.iter().map(|r| r.avg).collect::<Vec<f64>>();
+    match kind {
+        // The last 6 hours, weighted towards the most recent 30 minutes.
+        BaselineKind::Ewma => {
+            let values = averages(history.minute_rollups(series, current_minute - 6 * 3600, current_minute));
+            (values.len() >= MIN_BASELINE_POINTS).then(|| ewma(&values, 30.0))
+        },
+        // The hour centred on this moment a week ago.
+        BaselineKind::Weekly => {
+            let last_week = current_minute - 7 * 86400;
+            let values = averages(history.minute_rollups(series, last_week - 1800, last_week + 1800));
+            if values.len() >= MIN_BASELINE_POINTS {
+                return Some(mean_and_std(&values));
+            }
+            // Minute rollups are kept for 7 days by default, so a week back they
+            // are often gone; use the hourly averages of the surrounding hours.
+            let hours = averages(history.hour_rollups(series, last_week - 3 * 3600, last_week + 3 * 3600));
+            (hours.len() >= MIN_HOURLY_POINTS).then(|| mean_and_std(&hours))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::HistoryConfig;
+    use crate::history::{Point, Tier};
+
+    #[test]
+    fn ewma_of_empty_and_single_input() {
+        let empty = ewma(&[], 30.0);
+        assert_eq!((empty.mean, empty.std_dev), (0.0, 0.0));
+        let single = ewma(&[7.0], 30.0);
+        assert_eq!((single.mean, single.std_dev), (7.0, 0.0));
+    }
+
+    #[test]
+    fn ewma_follows_recent_values() {
+        let flat = ewma(&[5.0; 50], 30.0);
+        assert_eq!((flat.mean, flat.std_dev), (5.0, 0.0));
+
+        let mut values = vec![10.0; 60];
+        values.extend([20.0; 30]);
+        let stepped = ewma(&values, 30.0);
+        assert!(stepped.mean > 15.0 && stepped.mean < 20.0);
+        assert!(stepped.std_dev > 0.0);
+        // A span of one point forgets everything but the last value.
+        assert_eq!(ewma(&[1.0, 2.0, 3.0], 1.0).mean, 3.0);
+    }
+
+    #[test]
+    fn z_score_floors_the_deviation() {
+        let baseline = mean_and_std(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!((baseline.mean, baseline.std_dev), (5.0, 2.0));
+        assert_eq!(baseline.z_score(1.0), -2.0);
+        let flat = Baseline { mean: 100.0, std_dev: 0.0 };
+        assert_eq!(flat.z_score(102.0), 2.0);
+    }
+
+    fn hour_point(ts: i64, value: f64) -> Point {
+        Point { ts, value, min: None, max: None, p95: None, count: Some(60) }
+    }
+
+    #[test]
+    fn weekly_baseline_falls_back_to_hour_rollups() {
+        let mut history = HistoryStore::open(&HistoryConfig { enabled: Some(false), ..Default::default() });
+        let now = chrono::Utc::now().timestamp();
+        let last_week = now - now.rem_euclid(3600) - 7 * 86400;
+        // Only the hour tier reaches back a week; the minute rollups have expired.
+        let points: Vec<_> = (-1..3).map(|hour| hour_point(last_week + hour * 3600, if hour % 2 == 0 { 40.0 } else { 60.0 })).collect();
+        history.backfill("cpu", Tier::Hour, &points);
+        assert!(history.minute_rollups("cpu", 0, now).is_empty());
+
+        let expected = baseline(&history, "cpu", BaselineKind::Weekly, now).unwrap();
+        assert_eq!((expected.mean, expected.std_dev), (50.0, 10.0));
+        assert!(baseline(&history, "cpu", BaselineKind::Ewma, now).is_none());
+        // Too few hours is still no baseline.
+        let mut sparse = HistoryStore::open(&HistoryConfig { enabled: Some(false), ..Default::default() });
+        sparse.backfill("cpu", Tier::Hour, &points[..2]);
+        assert!(baseline(&sparse, "cpu", BaselineKind::Weekly, now).is_none());
+    }
+
+    #[test]
+    fn no_baseline_without_history() {
+        let history = HistoryStore::open(&HistoryConfig { enabled: Some(false), ..Default::default() });
+        assert!(baseline(&history, "cpu", BaselineKind::Ewma, 1_000_000).is_none());
+        assert_eq!(BaselineKind::from_name("weekly").map(|kind| kind.name()), Some("weekly"));
+        assert!(BaselineKind::from_name("monthly").is_none());
+    }
+}
@@ -0,0 +1,169 @@
+// src/certs.rs
+
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, RwLock};
+use openssl::asn1::Asn1Time;
+use openssl::nid::Nid;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::{X509, X509NameRef};
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+use crate::config_manager::{CertificateConfig, Config};
+use crate::system_info::SystemData;
+
+/// Details of a single certificate, read from disk or from a live TLS endpoint.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct CertificateInfo {
+    // The file path or "host:port" the certificate came from.
+    pub source: String,
+    pub subject: String,
+    pub sans: Vec<String>,
+    pub issuer: String,
+    pub not_after: String,
+    // Negative once the certificate has expired.
+    pub days_until_expiry: i32,
+    // Set instead of the fields above when the certificate could not be read.
+    pub error: Option<String>,
+}
+
+/// Formats an X509 name as "CN=example.com, O=Example".
+fn name_to_string(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().as_utf8().map(|v| v.to_string()).unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_certificate(source: &str, cert: &X509) -> CertificateInfo {
+    let subject = cert.subject_name().entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok().map(|v| v.to_string()))
+        .unwrap_or_else(|| name_to_string(cert.subject_name()));
+
+    let sans = cert.subject_alt_names()
+        .map(|names| names.iter().filter_map(|name| {
+            name.dnsname().map(|dns| dns.to_string()).or_else(|| {
+                name.ipaddress().and_then(|ip| match ip.len() {
+                    4 => <[u8; 4]>::try_from(ip).ok().map(|b| std::net::Ipv4Addr::from(b).to_string()),
+                    16 => <[u8; 16]>::try_from(ip).ok().map(|b| std::net::Ipv6Addr::from(b).to_string()),
+                    _ => None,
+                })
+            })
+        }).collect())
+        .unwrap_or_default();
+
+    let days_until_expiry = Asn1Time::days_from_now(0)
+        .and_then(|now| now.diff(cert.not_after()))
+        .map(|diff| diff.days)
+        .unwrap_or(0);
+
+    CertificateInfo {
+        source: source.to_string(),
+        subject,
+        sans,
+        issuer: name_to_string(cert.issuer_name()),
+        not_after: cert.not_after().to_string(),
+        days_until_expiry,
+        error: None,
+    }
+}
+
+fn failed(source: &str, error: String) -> CertificateInfo {
+    CertificateInfo {
+        source: source.to_string(),
+        error: Some(error),
+        ..Default::default()
+    }
+}
+
+/// Reads the leaf certificate from a PEM file (the first one in a chain).
+fn read_certificate_file(path: &str) -> CertificateInfo {
+    let pem = match std::fs::read(path) {
+        Ok(pem) => pem,
+        Err(e) => return failed(path, e.to_string()),
+    };
+    match X509::stack_from_pem(&pem) {
+        Ok(certs) => match certs.first() {
+            Some(cert) => describe_certificate(path, cert),
+            None => failed(path, "no certificate found in file".into()),
+        },
+        Err(e) => failed(path, e.to_string()),
+    }
+}
+
+/// Connects to a "host:port" endpoint and reads the certificate it presents.
+/// Verification is disabled on purpose: we want to report on expired or
+/// self-signed certificates rather than fail to read them.
+fn fetch_endpoint_certificate(endpoint: &str) -> CertificateInfo {
+    let address = if endpoint.contains(':') { endpoint.to_string() } else { format!("{}:443", endpoint) };
+    let host = address.rsplit_once(':').map(|(host, _)| host).unwrap_or(&address);
+
+    let result = (|| -> Result<X509, Box<dyn std::error::Error>> {
+        let socket_addr = address.to_socket_addrs()?.next().ok_or("could not resolve host")?;
+        let stream = TcpStream::connect_timeout(&socket_addr, std::time::Duration::from_secs(10))?;
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(10)))?;
+        stream.set_write_timeout(Some(std::time::Duration::from_secs(10)))?;
+
+        let mut builder = SslConnector::builder(SslMethod::tls())?;
+        builder.set_verify(SslVerifyMode::NONE);
+        let connector = builder.build();
+        let ssl_stream = connector.configure()?
+            .verify_hostname(false)
+            .connect(host, stream)?;
+        ssl_stream.ssl().peer_certificate().ok_or_else(|| "endpoint presented no certificate".into())
+    })();
+
+    match result {
+        Ok(cert) => describe_certificate(endpoint, &cert),
+        Err(e) => failed(endpoint, e.to_string()),
+    }
+}
+
+/// Expands the configured globs and endpoints into certificate reports.
+fn check_certificates(settings: &CertificateConfig) -> Vec<CertificateInfo> {
+    let mut results = Vec::new();
+    for pattern in settings.paths.iter().flatten() {
+        match glob::glob(pattern) {
+            Ok(paths) => {
+                for path in paths.flatten() {
+                    results.push(read_certificate_file(&path.to_string_lossy()));
+                }
+            },
+            Err(e) => results.push(failed(pattern, e.to_string())),
+        }
+    }
+    for endpoint in settings.endpoints.iter().flatten() {
+        results.push(fetch_endpoint_certificate(endpoint));
+    }
+    results
+}
+
+/// Periodically re-reads the configured certificates.
+pub async fn poll_certificates(
+    data: Arc<Mutex<SystemData>>,
+    config_arc: Arc<RwLock<Config>>,
+    mut shutdown: watch::Receiver<bool>
+) {
+    loop {
+        let settings = config_arc.read().unwrap().certificates.clone().unwrap_or_default();
+        let interval = settings.check_secs.unwrap_or(3600).max(60);
+        if let Ok(certificates) = tokio::task::spawn_blocking(move || check_certificates(&settings)).await {
+            data.lock().unwrap().certificates = certificates;
+        }
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down certificate checker.");
+                    break;
+                }
+            },
+            _ = sleep(Duration::from_secs(interval)) => {}
+        }
+    }
+}
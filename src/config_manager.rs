@@ -1,6 +1,8 @@
 // src/config_manager.rs
 
 use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use notify::{Watcher, RecursiveMode, watcher, DebouncedEvent};
 use std::sync::mpsc::{channel, TryRecvError};
@@ -13,12 +15,185 @@ pub struct RemotePylonConfig {
     pub ip: String,
     pub port: u16,
     pub token: String,
+    // The remote's pylon id, learned on the first successful poll.
+    pub id: Option<String>,
     // New optional name for a remote pylon.
     pub name: Option<String>,
     // New: location string (e.g. "Tampa, FL")
     pub location: Option<String>,
     // New: description string
     pub description: Option<String>,
+    // Seconds between polls of this remote (default from `[remote_defaults]`).
+    pub poll_interval: Option<u64>,
+    // Request timeout in seconds.
+    pub timeout: Option<u64>,
+    // First retry delay in seconds once the remote is offline; doubled after
+    // every further failure.
+    pub retry_backoff: Option<u64>,
+    // Added by peer discovery; such remotes expire when not reached for
+    // `discovery_expiry_days`.
+    pub discovered: Option<bool>,
+    // Last successful poll of a discovered remote (updated at most daily).
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// Polling settings for remotes that do not set their own (the
+/// `[remote_defaults]` table).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RemoteDefaults {
+    // Seconds between polls (default 10).
+    pub poll_interval: Option<u64>,
+    // Request timeout in seconds (default 5).
+    pub timeout: Option<u64>,
+    // First retry delay in seconds for offline remotes (default: the poll interval).
+    pub retry_backoff: Option<u64>,
+    // Upper bound of the backoff delay in seconds (default 300).
+    pub max_backoff: Option<u64>,
+}
+
+/// A command that reports the installed version of some piece of software.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SoftwareProbe {
+    // The executable to run (looked up on PATH).
+    pub command: String,
+    // Arguments passed to the command, e.g. ["--version"].
+    pub args: Option<Vec<String>>,
+    // Optional regex applied to the output; the first capture group (or the
+    // whole match) becomes the version. Without it the first line is used.
+    pub regex: Option<String>,
+}
+
+impl SoftwareProbe {
+    fn new(command: &str, arg: &str) -> Self {
+        Self {
+            command: command.into(),
+            args: Some(vec![arg.into()]),
+            regex: None,
+        }
+    }
+}
+
+/// The probes used when `[software]` is missing from config.toml.
+pub fn default_software_probes() -> HashMap<String, SoftwareProbe> {
+    let mut probes = HashMap::new();
+    probes.insert("apache".into(), SoftwareProbe::new("apache2", "-v"));
+    probes.insert("php".into(), SoftwareProbe::new("php", "-v"));
+    probes.insert("mariadb".into(), SoftwareProbe::new("mysql", "--version"));
+    probes.insert("rust".into(), SoftwareProbe::new("rustc", "--version"));
+    probes.insert("node".into(), SoftwareProbe::new("node", "-v"));
+    probes.insert("npm".into(), SoftwareProbe::new("npm", "-v"));
+    probes
+}
+
+/// TLS certificates to watch for expiry (the `[certificates]` table).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CertificateConfig {
+    // PEM files or globs, e.g. "/etc/letsencrypt/live/*/cert.pem".
+    pub paths: Option<Vec<String>>,
+    // Live TLS endpoints as "host:port" (port defaults to 443).
+    pub endpoints: Option<Vec<String>>,
+    // How often (in seconds) certificates are re-read.
+    pub check_secs: Option<u64>,
+}
+
+/// A log file to tail (one `[[log_watches]]` entry).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LogWatchConfig {
+    pub path: String,
+    // Regexes whose matches are counted per minute.
+    pub patterns: Vec<String>,
+    // How many recent matching lines to keep (default 50).
+    pub keep_lines: Option<usize>,
+}
+
+/// Settings for the embedded metrics history (the `[history]` table).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HistoryConfig {
+    // Set to false to keep history in memory only.
+    pub enabled: Option<bool>,
+    // Directory holding the history files (default "history").
+    pub dir: Option<String>,
+    // How long raw samples are kept (default 1 hour).
+    pub raw_retention_hours: Option<u64>,
+    // How long 1-minute rollups are kept (default 7 days).
+    pub minute_retention_days: Option<u64>,
+    // How long 1-hour rollups are kept (default 365 days).
+    pub hour_retention_days: Option<u64>,
+}
+
+/// Debouncing and flap detection for remote pylons and services (the
+/// `[hysteresis]` table).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HysteresisConfig {
+    // Consecutive failed checks before an entity is considered down (default 3).
+    pub failures_before_down: Option<u32>,
+    // Consecutive successful checks before it is considered up again (default 2).
+    pub successes_before_up: Option<u32>,
+    // Window in which state changes are counted (default 600 seconds).
+    pub flap_window_secs: Option<u64>,
+    // State changes within the window that mark an entity as flapping (default 4).
+    pub flap_transitions: Option<u32>,
+}
+
+/// An alerting rule (one `[[alerts]]` entry).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlertRuleConfig {
+    // Unique rule name, used in alert ids.
+    pub name: String,
+    // e.g. "cpu_usage > 90 for 5m", "service apache2 down", "remote offline for 2m".
+    pub rule: String,
+    // Free-form severity label (default "warning").
+    pub severity: Option<String>,
+    pub description: Option<String>,
+    // Extra labels attached to the alert, usable by silences and maintenance windows.
+    pub labels: Option<HashMap<String, String>>,
+}
+
+/// A recurring maintenance window (one `[[maintenance]]` entry). Alerts
+/// matching it are still tracked but no notifications are sent.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceWindow {
+    pub name: String,
+    // Weekdays the window opens on, e.g. ["sat", "sun"] (default: every day).
+    pub days: Option<Vec<String>>,
+    // Local start time as "HH:MM".
+    pub start: String,
+    // Length of the window, e.g. "2h".
+    pub duration: String,
+    // Matchers; a window without any applies to every alert.
+    pub pylon: Option<String>,
+    pub rule: Option<String>,
+    pub labels: Option<HashMap<String, String>>,
+}
+
+/// An alert notification channel (one `[[notifiers]]` entry).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotifierConfig {
+    // Unique channel name, used by the test-send endpoint.
+    pub name: String,
+    // "webhook", "slack", "discord", "mattermost", "email", "ntfy" or "gotify".
+    pub kind: String,
+    // Target URL (webhooks, ntfy topic URL, Gotify server URL).
+    pub url: Option<String>,
+    // Webhook body template; {{field}} placeholders are replaced with alert fields.
+    pub body: Option<String>,
+    // Extra HTTP headers for webhooks.
+    pub headers: Option<HashMap<String, String>>,
+    // ntfy access token or Gotify application token.
+    pub token: Option<String>,
+    // SMTP settings for "email".
+    pub smtp_host: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    // Set to false for plain SMTP (e.g. a local relay); defaults to STARTTLS.
+    pub smtp_starttls: Option<bool>,
+    pub from: Option<String>,
+    pub to: Option<Vec<String>>,
+    // Attempts per notification (default 3).
+    pub retries: Option<u32>,
+    // Notifications allowed per hour before further ones are dropped (default 30).
+    pub max_per_hour: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,6 +202,9 @@ pub struct Config {
     pub local_port: Option<u16>,
     // The token that remote clients must supply when polling /api/metrics.
     pub token: String,
+    // Persistent UUID of this pylon, generated on first run. Other pylons key
+    // their view of this one by it, so a new IP does not make a new pylon.
+    pub id: Option<String>,
     // New: the name of this local Pylon.
     pub name: Option<String>,
     // A list of remote Pylon instances to poll.
@@ -42,8 +220,54 @@ pub struct Config {
 
     // NEW: List of service names to display (e.g. "apache2", "mariadb")
     pub services: Option<Vec<String>>,
-    // NEW: List of software version keys to show (e.g. "os_version", "apache_version", etc.)
-    pub software_versions: Option<Vec<String>>,
+    // Software version probes keyed by display name (the `[software]` table).
+    pub software: Option<HashMap<String, SoftwareProbe>>,
+    // How often (in seconds) the software versions are re-probed.
+    pub software_refresh_secs: Option<u64>,
+    // How often (in seconds) pending OS package updates are checked.
+    pub package_check_secs: Option<u64>,
+    // Certificate files and endpoints to monitor.
+    pub certificates: Option<CertificateConfig>,
+    // Log files to tail for matching lines.
+    pub log_watches: Option<Vec<LogWatchConfig>>,
+    // How often (in seconds) listening sockets and TCP states are collected.
+    pub socket_check_secs: Option<u64>,
+    // Metrics history storage settings.
+    pub history: Option<HistoryConfig>,
+    // Threshold alert rules.
+    pub alerts: Option<Vec<AlertRuleConfig>>,
+    // Channels that firing and resolved alerts are sent to.
+    pub notifiers: Option<Vec<NotifierConfig>>,
+    // Recurring windows during which alert notifications are suppressed.
+    pub maintenance: Option<Vec<MaintenanceWindow>>,
+    // Debouncing of remote and service up/down checks.
+    pub hysteresis: Option<HysteresisConfig>,
+    // Remotes polled at the same time (default 16).
+    pub remote_concurrency: Option<usize>,
+    // Seconds a remote removed from remote_pylons stays listed as removed (default 300).
+    pub removed_remote_grace: Option<u64>,
+    // Polling settings for remotes that do not set their own.
+    pub remote_defaults: Option<RemoteDefaults>,
+    // Central pylon this pylon pushes its metrics to, e.g. "http://10.0.0.1:6989".
+    pub upstream: Option<String>,
+    // The token of the upstream pylon.
+    pub upstream_token: Option<String>,
+    // How often (in seconds) metrics are pushed upstream (default 10).
+    pub upstream_interval: Option<u64>,
+    // Levels of remotes below this pylon expanded by /api/federate (default 3).
+    pub federation_max_depth: Option<u32>,
+    // How peers advertised by remotes are handled: "off", "manual" (default) or "auto".
+    pub discovery: Option<String>,
+    // Days after which unreachable discovered remotes are removed (default 30).
+    pub discovery_expiry_days: Option<u64>,
+    // Announce this pylon on the local network and list the pylons heard there.
+    pub lan_discovery: Option<bool>,
+    // UDP port of the LAN beacon (default 6990).
+    pub lan_port: Option<u16>,
+    // Address beacons are sent to (default "255.255.255.255").
+    pub lan_broadcast: Option<String>,
+    // Shared secret that lets pylons on the LAN adopt each other with one click.
+    pub lan_secret: Option<String>,
 }
 
 impl Default for Config {
@@ -51,6 +275,7 @@ impl Default for Config {
         Self {
             local_port: Some(6989),
             token: "default_token".into(),
+            id: None,
             name: Some("Local Pylon".into()),
             remote_pylons: None,
             description: None,
@@ -58,19 +283,64 @@ impl Default for Config {
             auto_update: Some(false), // disabled by default
             master_update_url: Some("https://brinstar.top/pylon".into()),
             services: Some(vec!["apache2".into(), "mariadb".into()]),
-            software_versions: Some(vec![
-                "os_version".into(),
-                "apache_version".into(),
-                "php_version".into(),
-                "mariadb_version".into(),
-                "rust_version".into(),
-                "node_version".into(),
-                "npm_version".into(),
-            ]),
+            software: Some(default_software_probes()),
+            software_refresh_secs: Some(3600),
+            package_check_secs: Some(3600),
+            certificates: None,
+            log_watches: None,
+            socket_check_secs: Some(10),
+            history: None,
+            alerts: None,
+            notifiers: None,
+            maintenance: None,
+            hysteresis: None,
+            remote_concurrency: None,
+            removed_remote_grace: None,
+            remote_defaults: None,
+            upstream: None,
+            upstream_token: None,
+            upstream_interval: None,
+            federation_max_depth: None,
+            discovery: None,
+            discovery_expiry_days: None,
+            lan_discovery: None,
+            lan_port: None,
+            lan_broadcast: None,
+            lan_secret: None,
         }
     }
 }
 
+/// A random (version 4) UUID.
+pub fn generate_id() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// The "ip:port" a remote is polled at.
+pub fn address(remote: &RemotePylonConfig) -> String {
+    format!("{}:{}", remote.ip, remote.port)
+}
+
+/// Parses a duration such as "90", "30s", "5m", "2h" or "7d" into seconds.
+pub fn parse_duration(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: i64 = number.parse().ok()?;
+    let multiplier = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
 pub fn load_config() -> Result<Config, config::ConfigError> {
     let settings = config::Config::builder()
         .add_source(config::File::with_name("config"))
@@ -79,6 +349,7 @@ pub fn load_config() -> Result<Config, config::ConfigError> {
 }
 
 /// Now accepts a shutdown receiver so it can exit gracefully.
+#[allow(clippy::single_match)]
 pub async fn watch_config(config_arc: Arc<RwLock<Config>>, shutdown: watch::Receiver<bool>) {
     let (tx, rx) = channel();
     let mut watcher = watcher(tx, Duration::from_secs(2)).unwrap();
@@ -97,8 +368,12 @@ pub async fn watch_config(config_arc: Arc<RwLock<Config>>, shutdown: watch::Rece
             Ok(event) => match event {
                 DebouncedEvent::Write(path) => {
                     println!("Config file changed: {:?}", path);
-                    if let Ok(new_config) = load_config() {
+                    if let Ok(mut new_config) = load_config() {
                         let mut config_lock = config_arc.write().unwrap();
+                        // Other pylons know us by our id; it must not change while running.
+                        if new_config.id.is_none() {
+                            new_config.id = config_lock.id.clone();
+                        }
                         *config_lock = new_config;
                         println!("Config reloaded.");
                     } else {
@@ -118,6 +393,7 @@ pub async fn watch_config(config_arc: Arc<RwLock<Config>>, shutdown: watch::Rece
     }
 }
 
+#[allow(clippy::io_other_error)]
 pub fn save_config(config: &Config) -> Result<(), std::io::Error> {
     let toml_str = toml::to_string_pretty(&config)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
@@ -0,0 +1,288 @@
+// src/discovery.rs
+
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::time::sleep;
+use crate::config_manager::{address, save_config, Config, RemotePylonConfig};
+use crate::remote::remote_key;
+
+const DISCOVERY_FILE: &str = "discovery.json";
+const DEFAULT_EXPIRY_DAYS: i64 = 30;
+
+/// How peers advertised by our remotes (their `remote_pylons`) are handled,
+/// from the `discovery` setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiscoveryMode {
+    // Advertised peers are ignored.
+    Off,
+    // Advertised peers wait for an admin to approve them (the default).
+    Manual,
+    // Advertised peers that come with a credential (from pairing or LAN
+    // adoption) are added to `remote_pylons` right away; others still need a
+    // token and wait for approval.
+    Auto,
+}
+
+impl DiscoveryMode {
+    pub fn from_config(config: &Config) -> DiscoveryMode {
+        match config.discovery.as_deref() {
+            Some("off") => DiscoveryMode::Off,
+            Some("auto") => DiscoveryMode::Auto,
+            _ => DiscoveryMode::Manual,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiscoveryMode::Off => "off",
+            DiscoveryMode::Manual => "manual",
+            DiscoveryMode::Auto => "auto",
+        }
+    }
+}
+
+/// A peer advertised by one of our remotes, waiting for approval.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PendingPeer {
+    // Pylon id of the peer when its advertiser knows it, else "ip:port".
+    pub key: String,
+    pub remote: RemotePylonConfig,
+    // The remote that advertised it.
+    pub via: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Peers awaiting approval and rejected peers, persisted to discovery.json.
+/// Approved peers live in `remote_pylons` in config.toml, marked `discovered`.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Discovery {
+    pub pending: Vec<PendingPeer>,
+    // Keys (pylon id or "ip:port") of peers an admin rejected; they are not offered again.
+    pub rejected: Vec<String>,
+}
+
+pub type SharedDiscovery = Arc<Mutex<Discovery>>;
+
+fn expiry(config: &Config) -> Duration {
+    Duration::days(config.discovery_expiry_days.map(|days| days as i64).unwrap_or(DEFAULT_EXPIRY_DAYS).max(1))
+}
+
+/// Whether `remote` is `peer`, by pylon id or by address.
+fn same_pylon(remote: &RemotePylonConfig, peer: &RemotePylonConfig) -> bool {
+    (remote.id.is_some() && remote.id == peer.id) || address(remote) == address(peer)
+}
+
+fn save(config: &Config) {
+    if let Err(e) = save_config(config) {
+        println!("Failed to save config.toml: {}", e);
+    }
+}
+
+impl Discovery {
+    pub fn load() -> Self {
+        match std::fs::read_to_string(DISCOVERY_FILE) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                println!("Failed to parse {}: {}", DISCOVERY_FILE, e);
+                Discovery::default()
+            }),
+            Err(_) => Discovery::default(),
+        }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(std::io::Error::other)
+            .and_then(|text| std::fs::write(DISCOVERY_FILE, text));
+        if let Err(e) = result {
+            println!("Failed to save {}: {}", DISCOVERY_FILE, e);
+        }
+    }
+
+    /// Handles a peer advertised by the remote `via`, according to the
+    /// discovery mode.
+    pub fn advertise(&mut self, config_arc: &Arc<RwLock<Config>>, mut peer: RemotePylonConfig, via: &str) {
+        let key = remote_key(&peer);
+        if key == via || self.rejected.contains(&key) || self.rejected.contains(&address(&peer)) {
+            return;
+        }
+        let mut config = config_arc.write().unwrap();
+        let mode = DiscoveryMode::from_config(&config);
+        // Our own entry in the advertiser's list.
+        if peer.id.is_some() && peer.id == config.id {
+            return;
+        }
+        if config.remote_pylons.iter().flatten().any(|r| same_pylon(r, &peer)) {
+            // Added by hand (or by auto mode) meanwhile.
+            self.forget_pending(&key);
+            return;
+        }
+        if mode == DiscoveryMode::Off {
+            return;
+        }
+        let now = Utc::now();
+        // Without a token the peer would only ever answer 401.
+        if mode == DiscoveryMode::Auto && !peer.token.is_empty() {
+            peer.discovered = Some(true);
+            peer.last_seen = Some(now);
+            config.remote_pylons.get_or_insert_with(Vec::new).push(peer);
+            save(&config);
+            self.forget_pending(&key);
+            println!("Discovered new peer: {} (via {})", key, via);
+            return;
+        }
+        match self.pending.iter_mut().find(|pending| pending.key == key) {
+            Some(pending) => {
+                pending.last_seen = now;
+                pending.remote = peer;
+            },
+            None => {
+                println!("Peer {} advertised by {} is awaiting approval", key, via);
+                self.pending.push(PendingPeer { key, remote: peer, via: via.to_string(), first_seen: now, last_seen: now });
+                self.save();
+            }
+        }
+    }
+
+    fn forget_pending(&mut self, key: &str) {
+        if self.pending.iter().any(|pending| pending.key == key) {
+            self.pending.retain(|pending| pending.key != key);
+            self.save();
+        }
+    }
+
+    /// Moves a pending peer into `remote_pylons`, polled with `token` when
+    /// given. A peer that came without a credential needs one.
+    pub fn approve(&mut self, config_arc: &Arc<RwLock<Config>>, key: &str, token: Option<String>) -> Result<(), String> {
+        let index = self.pending.iter().position(|pending| pending.key == key)
+            .ok_or_else(|| format!("no pending peer '{}'", key))?;
+        let token = token.filter(|token| !token.is_empty());
+        if token.is_none() && self.pending[index].remote.token.is_empty() {
+            return Err(format!("'{}' needs its token or a credential it issued (or pair with it)", key));
+        }
+        let mut peer = self.pending.remove(index).remote;
+        if let Some(token) = token {
+            peer.token = token;
+        }
+        peer.discovered = Some(true);
+        peer.last_seen = Some(Utc::now());
+        let mut config = config_arc.write().unwrap();
+        config.remote_pylons.get_or_insert_with(Vec::new).push(peer);
+        save_config(&config).map_err(|e| e.to_string())?;
+        self.save();
+        println!("Approved discovered peer {}", key);
+        Ok(())
+    }
+
+    /// Drops a pending peer and stops offering it.
+    pub fn reject(&mut self, key: &str) {
+        self.pending.retain(|pending| pending.key != key);
+        if !self.rejected.iter().any(|rejected| rejected == key) {
+            self.rejected.push(key.to_string());
+        }
+        self.save();
+    }
+
+    /// Forgets a rejection, so the peer is offered again when advertised.
+    pub fn unreject(&mut self, key: &str) {
+        self.rejected.retain(|rejected| rejected != key);
+        self.save();
+    }
+
+    /// Drops pending peers no longer advertised within the expiry period.
+    fn expire(&mut self, expiry: Duration, now: DateTime<Utc>) {
+        let before = self.pending.len();
+        self.pending.retain(|pending| now - pending.last_seen < expiry);
+        if self.pending.len() != before {
+            println!("Expired {} pending peer(s)", before - self.pending.len());
+            self.save();
+        }
+    }
+}
+
+/// Records a successful poll of a discovered remote. Written to config.toml
+/// at most once a day, which is plenty for an expiry counted in days.
+pub fn mark_seen(config_arc: &Arc<RwLock<Config>>, key: &str) {
+    let now = Utc::now();
+    let stale = |remote: &RemotePylonConfig| {
+        remote.discovered == Some(true)
+            && remote_key(remote) == key
+            && remote.last_seen.is_none_or(|seen| now - seen >= Duration::days(1))
+    };
+    if !config_arc.read().unwrap().remote_pylons.iter().flatten().any(stale) {
+        return;
+    }
+    let mut config = config_arc.write().unwrap();
+    if let Some(remote) = config.remote_pylons.iter_mut().flatten().find(|remote| stale(remote)) {
+        remote.last_seen = Some(now);
+        save(&config);
+    }
+}
+
+/// Removes discovered remotes that have not been reached, and pending peers
+/// that have not been advertised, for `discovery_expiry_days`. Remotes added
+/// by hand never expire. Runs hourly.
+pub async fn expire_peers(config_arc: Arc<RwLock<Config>>, discovery: SharedDiscovery, mut shutdown: watch::Receiver<bool>) {
+    loop {
+        let now = Utc::now();
+        let expiry = {
+            let mut config = config_arc.write().unwrap();
+            let expiry = expiry(&config);
+            if let Some(remotes) = config.remote_pylons.as_mut() {
+                let before = remotes.len();
+                remotes.retain(|remote| {
+                    let expired = remote.discovered == Some(true) && remote.last_seen.is_some_and(|seen| now - seen >= expiry);
+                    if expired {
+                        println!("Removing discovered peer {}:{} (not seen for {} days)", remote.ip, remote.port, expiry.num_days());
+                    }
+                    !expired
+                });
+                if remotes.len() != before {
+                    save(&config);
+                }
+            }
+            expiry
+        };
+        // Not under the config lock: advertise() takes the discovery lock first.
+        discovery.lock().unwrap().expire(expiry, now);
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down peer expiry.");
+                    break;
+                }
+            },
+            _ = sleep(std::time::Duration::from_secs(3600)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(token: &str) -> PendingPeer {
+        PendingPeer {
+            key: "10.0.0.7:6989".into(),
+            remote: serde_json::from_value(serde_json::json!({"ip": "10.0.0.7", "port": 6989, "token": token})).unwrap(),
+            via: "10.0.0.2:6989".into(),
+            first_seen: Utc::now(),
+            last_seen: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn approval_needs_a_token_for_adverts() {
+        let config = Arc::new(RwLock::new(Config::default()));
+        let mut discovery = Discovery { pending: vec![pending("")], ..Default::default() };
+        let error = discovery.approve(&config, "10.0.0.7:6989", Some(String::new())).unwrap_err();
+        assert!(error.contains("needs its token"));
+        // Still offered, and nothing was added.
+        assert_eq!(discovery.pending.len(), 1);
+        assert!(config.read().unwrap().remote_pylons.is_none());
+        assert!(discovery.approve(&config, "10.0.0.8:6989", None).unwrap_err().contains("no pending peer"));
+    }
+}
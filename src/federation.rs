@@ -0,0 +1,145 @@
+// src/federation.rs
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use futures::future::join_all;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::time::Duration;
+use crate::config_manager::{self, Config};
+use crate::remote::RemoteStatus;
+use crate::server::local_metrics;
+use crate::system_info::SystemData;
+
+/// Levels of children expanded below the pylon that is asked.
+const DEFAULT_MAX_DEPTH: u32 = 3;
+/// Time allowed for each level of the tree below a child.
+const TIMEOUT_PER_LEVEL: u64 = 5;
+/// Seconds the tree built for the dashboard is reused before it is rebuilt.
+const CACHE_SECS: u64 = 30;
+
+/// The last tree built for requests that are not from a peer (the dashboard).
+pub type SharedFederationCache = Arc<tokio::sync::Mutex<Option<(Instant, Value)>>>;
+
+#[derive(Deserialize)]
+pub struct FederateQuery {
+    // Levels of children to expand (capped by `federation_max_depth`).
+    pub depth: Option<u32>,
+    // Comma-separated pylon ids of the pylons above us in the tree.
+    pub visited: Option<String>,
+}
+
+/// Asks a child for its own subtree.
+async fn fetch_subtree(client: &Client, status: &RemoteStatus, token: &str, depth: u32, visited: &str) -> Result<Value, String> {
+    let url = format!("http://{}:{}/api/federate?depth={}&visited={}", status.ip, status.port, depth, visited);
+    let resp = client.get(&url)
+        .bearer_auth(token)
+        .timeout(Duration::from_secs(TIMEOUT_PER_LEVEL * (depth as u64 + 1)))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("HTTP {}", resp.status()));
+    }
+    resp.json::<Value>().await.map_err(|e| e.to_string())
+}
+
+/// One child: its status as we poll it and, when it can be reached, the
+/// subtree it reports itself (which carries its metrics, so they are dropped
+/// from the status).
+async fn child_node(client: &Client, key: String, mut status: RemoteStatus, token: String, depth: u32, visited: &str) -> Value {
+    let mut child = json!({"key": key});
+    // Pushing children cannot be reached from here, removed ones are no longer ours to ask.
+    if status.online && !status.pushed && status.removed_at.is_none() {
+        match fetch_subtree(client, &status, &token, depth, visited).await {
+            Ok(pylon) => {
+                status.data = None;
+                child["pylon"] = pylon;
+            },
+            Err(e) => child["error"] = json!(e),
+        }
+    }
+    child["status"] = json!(status);
+    child
+}
+
+/// Builds the federation tree rooted at this pylon: its own metrics plus the
+/// statuses of its remotes, each expanded with the subtree the remote reports
+/// from its own /api/federate, `depth` levels down. A pylon that finds its
+/// own id among `visited` answers with a loop marker instead of recursing.
+pub async fn federate(
+    config_arc: &Arc<RwLock<Config>>,
+    system_data: &Arc<Mutex<SystemData>>,
+    remote_statuses: &Arc<Mutex<HashMap<String, RemoteStatus>>>,
+    query: FederateQuery
+) -> Value {
+    let mut visited: Vec<&str> = query.visited.as_deref().unwrap_or_default()
+        .split(',')
+        .filter(|v| !v.is_empty())
+        .collect();
+    let (id, metrics, max_depth, tokens) = {
+        // Cloned so the config lock is not held while taking the SystemData lock.
+        let config = config_arc.read().unwrap().clone();
+        let tokens: HashMap<String, String> = config.remote_pylons.iter().flatten()
+            .map(|remote| (config_manager::address(remote), remote.token.clone()))
+            .collect();
+        let id = config.id.clone().unwrap_or_default();
+        (id, local_metrics(&system_data.lock().unwrap(), &config), config.federation_max_depth.unwrap_or(DEFAULT_MAX_DEPTH), tokens)
+    };
+
+    let mut node = json!({
+        "id": id,
+        "name": metrics["name"],
+        "location": metrics["location"],
+        "version": metrics["version"],
+    });
+    if visited.contains(&id.as_str()) {
+        node["loop"] = json!(true);
+        return node;
+    }
+    visited.push(&id);
+    let visited = visited.join(",");
+
+    let mut statuses: Vec<(String, RemoteStatus)> = remote_statuses.lock().unwrap()
+        .iter()
+        .map(|(key, status)| (key.clone(), status.clone()))
+        .collect();
+    statuses.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let depth = query.depth.unwrap_or(max_depth).min(max_depth);
+    node["metrics"] = metrics;
+    if depth == 0 {
+        // Children exist below this level but are not shown.
+        node["truncated"] = json!(!statuses.is_empty());
+        node["children"] = json!([]);
+        return node;
+    }
+    let client = Client::new();
+    let children = statuses.into_iter().map(|(key, status)| {
+        let token = tokens.get(&format!("{}:{}", status.ip, status.port)).cloned().unwrap_or_default();
+        child_node(&client, key, status, token, depth - 1, &visited)
+    });
+    node["children"] = json!(join_all(children).await);
+    node
+}
+
+/// The full-depth tree for the dashboard, rebuilt at most every `CACHE_SECS`
+/// however many dashboards are open. Concurrent requests wait for one build.
+pub async fn cached_federate(
+    cache: &SharedFederationCache,
+    config_arc: &Arc<RwLock<Config>>,
+    system_data: &Arc<Mutex<SystemData>>,
+    remote_statuses: &Arc<Mutex<HashMap<String, RemoteStatus>>>
+) -> Value {
+    let mut cache = cache.lock().await;
+    if let Some((built_at, tree)) = cache.as_ref() {
+        if built_at.elapsed().as_secs() < CACHE_SECS {
+            return tree.clone();
+        }
+    }
+    let tree = federate(config_arc, system_data, remote_statuses, FederateQuery { depth: None, visited: None }).await;
+    *cache = Some((Instant::now(), tree.clone()));
+    tree
+}
@@ -0,0 +1,131 @@
+// src/flapping.rs
+
+use std::collections::VecDeque;
+use crate::config_manager::HysteresisConfig;
+
+/// Debounces the up/down state of a remote pylon or service and detects
+/// flapping.
+///
+/// A single failed check no longer takes an entity down: it goes down after
+/// `failures_before_down` consecutive failures and comes back after
+/// `successes_before_up` consecutive successes. An entity is flapping while
+/// it changed state at least `flap_transitions` times within
+/// `flap_window_secs`.
+#[derive(Debug, Clone, Default)]
+pub struct StateTracker {
+    // None until the first check.
+    up: Option<bool>,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    transitions: u64,
+    // Timestamps of the transitions inside the flap window.
+    recent: VecDeque<i64>,
+    flapping: bool,
+}
+
+impl StateTracker {
+    /// Feeds one check result and returns the debounced state.
+    pub fn observe(&mut self, ok: bool, now: i64, settings: &HysteresisConfig) -> bool {
+        if ok {
+            self.consecutive_successes += 1;
+            self.consecutive_failures = 0;
+        } else {
+            self.consecutive_failures += 1;
+            self.consecutive_successes = 0;
+        }
+
+        let up = match self.up {
+            // The first check is taken as is.
+            None => ok,
+            Some(true) if self.consecutive_failures >= settings.failures_before_down.unwrap_or(3).max(1) => false,
+            Some(false) if self.consecutive_successes >= settings.successes_before_up.unwrap_or(2).max(1) => true,
+            Some(up) => up,
+        };
+        if self.up.is_some_and(|previous| previous != up) {
+            self.transitions += 1;
+            self.recent.push_back(now);
+        }
+        self.up = Some(up);
+
+        let window = settings.flap_window_secs.unwrap_or(600) as i64;
+        while self.recent.front().is_some_and(|ts| now - ts >= window) {
+            self.recent.pop_front();
+        }
+        self.flapping = self.recent.len() >= settings.flap_transitions.unwrap_or(4).max(1) as usize;
+        up
+    }
+
+    /// Whether the previous check failed, regardless of the debounced state.
+    pub fn last_check_failed(&self) -> bool {
+        self.consecutive_failures > 0
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// State changes since startup.
+    pub fn transitions(&self) -> u64 {
+        self.transitions
+    }
+
+    pub fn flapping(&self) -> bool {
+        self.flapping
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_first_check_as_is_then_debounces() {
+        let settings = HysteresisConfig::default();
+        let mut tracker = StateTracker::default();
+        assert!(!tracker.observe(false, 0, &settings));
+        assert!(!tracker.observe(true, 1, &settings));
+        assert!(tracker.observe(true, 2, &settings));
+        assert!(tracker.observe(false, 3, &settings));
+        assert!(tracker.observe(false, 4, &settings));
+        assert!(tracker.last_check_failed());
+        assert!(!tracker.observe(false, 5, &settings));
+        assert_eq!(tracker.consecutive_failures(), 3);
+        assert_eq!(tracker.transitions(), 2);
+    }
+
+    #[test]
+    fn zero_thresholds_act_as_one() {
+        let settings = HysteresisConfig {
+            failures_before_down: Some(0),
+            successes_before_up: Some(0),
+            ..Default::default()
+        };
+        let mut tracker = StateTracker::default();
+        assert!(tracker.observe(true, 0, &settings));
+        assert!(!tracker.observe(false, 1, &settings));
+        assert!(tracker.observe(true, 2, &settings));
+    }
+
+    #[test]
+    fn flaps_while_transitions_stay_inside_the_window() {
+        let settings = HysteresisConfig {
+            failures_before_down: Some(1),
+            successes_before_up: Some(1),
+            flap_window_secs: Some(60),
+            flap_transitions: Some(3),
+        };
+        let mut tracker = StateTracker::default();
+        tracker.observe(true, 0, &settings);
+        tracker.observe(false, 10, &settings);
+        tracker.observe(true, 20, &settings);
+        assert!(!tracker.flapping());
+        tracker.observe(false, 30, &settings);
+        assert!(tracker.flapping());
+        // The transition at 10 leaves the window at 70.
+        tracker.observe(false, 69, &settings);
+        assert!(tracker.flapping());
+        tracker.observe(false, 70, &settings);
+        assert!(!tracker.flapping());
+        assert_eq!(tracker.transitions(), 3);
+    }
+}
@@ -0,0 +1,157 @@
+// src/forecast.rs
+
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+use crate::history::{HistoryStore, SharedHistory};
+use crate::system_info::SystemData;
+
+/// How far back the usage trend is fitted.
+const FORECAST_WINDOW_SECS: i64 = 24 * 3600;
+/// Minute rollups needed before a forecast is made.
+const MIN_POINTS: usize = 10;
+/// The fit is O(n²), so longer histories are thinned to this many points.
+const MAX_POINTS: usize = 240;
+
+/// When a resource is expected to run out, from the trend of its usage.
+#[derive(Debug, Serialize, Clone)]
+pub struct Forecast {
+    // "disk:<mount>", "inodes:<mount>" or "swap".
+    pub key: String,
+    // "disk", "inodes" or "swap".
+    pub kind: String,
+    pub used: f64,
+    pub capacity: f64,
+    // Fitted growth of `used` per hour (negative while shrinking).
+    pub growth_per_hour: f64,
+    // None when usage is flat or shrinking.
+    pub hours_until_full: Option<f64>,
+    pub full_at: Option<DateTime<Utc>>,
+}
+
+/// Theil–Sen estimator: the median of the slopes between all pairs of
+/// points. Unlike least squares, a few outliers (a temporary file, a log
+/// rotation) barely move it.
+fn theil_sen_slope(points: &[(f64, f64)]) -> f64 {
+    let mut slopes = Vec::with_capacity(points.len() * points.len() / 2);
+    for (i, (x1, y1)) in points.iter().enumerate() {
+        for (x2, y2) in &points[i + 1..] {
+            if x2 != x1 {
+                slopes.push((y2 - y1) / (x2 - x1));
+            }
+        }
+    }
+    if slopes.is_empty() {
+        return 0.0;
+    }
+    slopes.sort_by(|a, b| a.total_cmp(b));
+    let mid = slopes.len() / 2;
+    if slopes.len() % 2 == 0 { (slopes[mid - 1] + slopes[mid]) / 2.0 } else { slopes[mid] }
+}
+
+/// Fits the usage trend of `series` and projects when it reaches `capacity`.
+fn forecast(history: &HistoryStore, key: String, kind: &str, series: &str, used: f64, capacity: f64, now: i64) -> Option<Forecast> {
+    let rollups = history.minute_rollups(series, now - FORECAST_WINDOW_SECS, now);
+    if rollups.len() < MIN_POINTS || capacity <= 0.0 {
+        return None;
+    }
+    let stride = rollups.len().div_ceil(MAX_POINTS);
+    let points: Vec<(f64, f64)> = rollups.iter().step_by(stride)
+        .map(|r| ((r.ts + 30) as f64, r.avg))
+        .collect();
+
+    let growth_per_hour = theil_sen_slope(&points) * 3600.0;
+    let hours_until_full = if growth_per_hour > 0.0 {
+        Some(((capacity - used) / growth_per_hour).max(0.0))
+    } else {
+        None
+    };
+    Some(Forecast {
+        key,
+        kind: kind.into(),
+        used,
+        capacity,
+        growth_per_hour,
+        hours_until_full,
+        full_at: hours_until_full.and_then(|hours| DateTime::from_timestamp(now + (hours * 3600.0) as i64, 0)),
+    })
+}
+
+/// Recomputes the disk, inode and swap forecasts once a minute (as often as
+/// new minute rollups appear).
+pub async fn poll_forecasts(
+    system_data: Arc<Mutex<SystemData>>,
+    history: SharedHistory,
+    mut shutdown: watch::Receiver<bool>
+) {
+    loop {
+        let polled = system_data.lock().unwrap().polled.clone();
+        let now = Utc::now().timestamp();
+        let forecasts: Vec<Forecast> = {
+            let history = history.lock().unwrap();
+            let mut forecasts = Vec::new();
+            for mount in &polled.mounts {
+                forecasts.extend(forecast(
+                    &history, format!("disk:{}", mount.mount_point), "disk",
+                    &format!("disk_used:{}", mount.mount_point),
+                    mount.total.saturating_sub(mount.available) as f64, mount.total as f64, now,
+                ));
+                if mount.inodes_total > 0 {
+                    forecasts.extend(forecast(
+                        &history, format!("inodes:{}", mount.mount_point), "inodes",
+                        &format!("inodes_used:{}", mount.mount_point),
+                        mount.inodes_total.saturating_sub(mount.inodes_free) as f64, mount.inodes_total as f64, now,
+                    ));
+                }
+            }
+            forecasts.extend(forecast(
+                &history, "swap".into(), "swap", "swap_used",
+                polled.swap_used as f64, polled.swap_total as f64, now,
+            ));
+            forecasts
+        };
+        system_data.lock().unwrap().forecasts = forecasts;
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down forecaster.");
+                    break;
+                }
+            },
+            _ = sleep(Duration::from_secs(60)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slope_of_too_few_points_is_flat() {
+        assert_eq!(theil_sen_slope(&[]), 0.0);
+        assert_eq!(theil_sen_slope(&[(1.0, 5.0)]), 0.0);
+        assert_eq!(theil_sen_slope(&[(1.0, 5.0), (1.0, 9.0)]), 0.0);
+    }
+
+    #[test]
+    fn slope_of_a_line() {
+        let points: Vec<(f64, f64)> = (0..10).map(|x| (x as f64, 2.0 * x as f64 + 1.0)).collect();
+        assert_eq!(theil_sen_slope(&points), 2.0);
+        let shrinking: Vec<(f64, f64)> = (0..10).map(|x| (x as f64, 100.0 - 0.5 * x as f64)).collect();
+        assert_eq!(theil_sen_slope(&shrinking), -0.5);
+    }
+
+    #[test]
+    fn slope_ignores_outliers_and_averages_even_medians() {
+        let mut points: Vec<(f64, f64)> = (0..20).map(|x| (x as f64, x as f64)).collect();
+        points[10].1 = 1000.0;
+        points[15].1 = -1000.0;
+        assert!((theil_sen_slope(&points) - 1.0).abs() < 0.01);
+        // The pair sharing an x is skipped, leaving slopes 1 and 3.
+        assert_eq!(theil_sen_slope(&[(0.0, 0.0), (1.0, 1.0), (1.0, 3.0)]), 2.0);
+    }
+}
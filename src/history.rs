@@ -0,0 +1,1002 @@
+// src/history.rs
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+use crate::config_manager::HistoryConfig;
+
+/// A single raw value of a series at a unix timestamp (seconds).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Sample {
+    pub ts: i64,
+    pub value: f64,
+}
+
+/// Summary of all samples in one bucket (a minute or an hour) starting at `ts`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Rollup {
+    pub ts: i64,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub p95: f64,
+    pub count: u64,
+}
+
+/// A point returned by `HistoryStore::query`. Rollup tiers also carry min/max/p95/count.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Point {
+    pub ts: i64,
+    pub value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p95: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+}
+
+impl Point {
+    fn to_rollup(self) -> Rollup {
+        Rollup {
+            ts: self.ts,
+            min: self.min.unwrap_or(self.value),
+            avg: self.value,
+            max: self.max.unwrap_or(self.value),
+            p95: self.p95.unwrap_or(self.value),
+            count: self.count.unwrap_or(1),
+        }
+    }
+}
+
+/// One line of the raw history file: every series recorded at `ts`.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    ts: i64,
+    values: HashMap<String, f64>,
+}
+
+/// One line of a rollup file.
+#[derive(Serialize, Deserialize)]
+struct RollupLine {
+    series: String,
+    #[serde(flatten)]
+    rollup: Rollup,
+}
+
+/// An online/offline state change of a monitored entity (e.g. a remote pylon).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Transition {
+    pub entity: String,
+    pub ts: i64,
+    pub online: bool,
+}
+
+/// Time spent online within a window, derived from recorded transitions.
+#[derive(Debug, Serialize, Clone)]
+pub struct Availability {
+    pub from: i64,
+    pub to: i64,
+    pub online_secs: i64,
+    pub offline_secs: i64,
+    // Seconds in the window before the first known state are not counted.
+    pub unknown_secs: i64,
+    pub uptime_percent: Option<f64>,
+    pub transitions: Vec<Transition>,
+}
+
+/// The storage tiers, from finest to coarsest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tier {
+    Raw,
+    Minute,
+    Hour,
+}
+
+impl Tier {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tier::Raw => "raw",
+            Tier::Minute => "1m",
+            Tier::Hour => "1h",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Tier> {
+        match name {
+            "raw" => Some(Tier::Raw),
+            "1m" => Some(Tier::Minute),
+            "1h" => Some(Tier::Hour),
+            _ => None,
+        }
+    }
+
+    fn bucket_secs(&self) -> i64 {
+        match self {
+            Tier::Raw => 1,
+            Tier::Minute => 60,
+            Tier::Hour => 3600,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    raw: VecDeque<Sample>,
+    minute: VecDeque<Rollup>,
+    hour: VecDeque<Rollup>,
+}
+
+/// An append-only JSON lines file that can be rewritten to drop old lines.
+struct TierFile {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl TierFile {
+    fn open(path: PathBuf) -> Self {
+        let writer = match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => Some(BufWriter::new(file)),
+            Err(e) => {
+                println!("Failed to open history file {:?}: {}", path, e);
+                None
+            }
+        };
+        Self { path, writer }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        match File::open(&self.path) {
+            Ok(file) => BufReader::new(file).lines().map_while(Result::ok).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn append<T: Serialize>(&mut self, value: &T) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Ok(line) = serde_json::to_string(value) {
+                let _ = writeln!(writer, "{}", line);
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writer.flush();
+        }
+    }
+
+    /// Starts rewriting the file: notes how much of it the compaction covers.
+    fn start_compaction(&mut self) -> Compaction {
+        self.flush();
+        Compaction {
+            path: self.path.clone(),
+            tmp_path: self.path.with_extension("tmp"),
+            len: std::fs::metadata(&self.path).map(|meta| meta.len()).unwrap_or(0),
+        }
+    }
+
+    /// Swaps the compacted file in, together with the lines appended since
+    /// the compaction started.
+    fn finish_compaction(&mut self, compaction: &Compaction) -> std::io::Result<()> {
+        self.flush();
+        let mut appended = File::open(&self.path)?;
+        appended.seek(SeekFrom::Start(compaction.len))?;
+        std::io::copy(&mut appended, &mut OpenOptions::new().append(true).open(&compaction.tmp_path)?)?;
+        std::fs::rename(&compaction.tmp_path, &self.path)?;
+        self.writer = Some(BufWriter::new(OpenOptions::new().append(true).open(&self.path)?));
+        Ok(())
+    }
+}
+
+/// A rewrite of one history file that drops old lines. The lines present when
+/// it started are filtered without holding the store's lock.
+struct Compaction {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    len: u64,
+}
+
+impl Compaction {
+    /// Writes the lines accepted by `keep` to the temporary file.
+    fn filter(&self, keep: impl Fn(&str) -> bool) -> std::io::Result<()> {
+        let reader = BufReader::new(File::open(&self.path)?.take(self.len));
+        let mut writer = BufWriter::new(File::create(&self.tmp_path)?);
+        for line in reader.lines().map_while(Result::ok).filter(|line| keep(line)) {
+            writeln!(writer, "{}", line)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Decides which lines of a history file survive a compaction.
+type KeepLine = Box<dyn Fn(&str) -> bool + Send>;
+/// Index of the transitions file in a compaction (after the three tier files).
+const TRANSITIONS_FILE: usize = 3;
+
+/// The `p`th percentile (0.0 - 1.0) of `values` using the nearest-rank method.
+fn percentile(values: &mut [f64], p: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let rank = ((p * values.len() as f64).ceil() as usize).clamp(1, values.len());
+    values[rank - 1]
+}
+
+fn rollup_samples(ts: i64, samples: &[Sample]) -> Rollup {
+    let mut values: Vec<f64> = samples.iter().map(|s| s.value).collect();
+    let sum: f64 = values.iter().sum();
+    Rollup {
+        ts,
+        min: values.iter().cloned().fold(f64::INFINITY, f64::min),
+        avg: sum / values.len() as f64,
+        max: values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+        p95: percentile(&mut values, 0.95),
+        count: values.len() as u64,
+    }
+}
+
+/// Combines finer rollups into one. The exact p95 cannot be recovered from
+/// rollups, so it is approximated by the p95 of the finer buckets' p95 values.
+fn merge_rollups(ts: i64, rollups: &[Rollup]) -> Rollup {
+    let count: u64 = rollups.iter().map(|r| r.count).sum();
+    let weighted: f64 = rollups.iter().map(|r| r.avg * r.count as f64).sum();
+    let mut p95s: Vec<f64> = rollups.iter().map(|r| r.p95).collect();
+    Rollup {
+        ts,
+        min: rollups.iter().map(|r| r.min).fold(f64::INFINITY, f64::min),
+        avg: if count > 0 { weighted / count as f64 } else { 0.0 },
+        max: rollups.iter().map(|r| r.max).fold(f64::NEG_INFINITY, f64::max),
+        p95: percentile(&mut p95s, 0.95),
+        count,
+    }
+}
+
+/// Inserts `item` keeping `items` sorted by timestamp. When an item with the
+/// same timestamp exists it is overwritten if `replace` is set, and kept
+/// otherwise. Returns whether `items` changed.
+fn insert_sorted<T>(items: &mut VecDeque<T>, item: T, ts: impl Fn(&T) -> i64, replace: bool) -> bool {
+    let key = ts(&item);
+    let idx = items.partition_point(|existing| ts(existing) < key);
+    match items.get_mut(idx) {
+        Some(existing) if ts(existing) == key => {
+            if replace {
+                *existing = item;
+            }
+            replace
+        },
+        _ => {
+            items.insert(idx, item);
+            true
+        }
+    }
+}
+
+/// Groups time-ordered items into consecutive buckets of `bucket_secs`.
+fn group_by_bucket<T: Copy>(items: impl Iterator<Item = T>, ts: impl Fn(&T) -> i64, bucket_secs: i64) -> Vec<(i64, Vec<T>)> {
+    let mut groups: Vec<(i64, Vec<T>)> = Vec::new();
+    for item in items {
+        let bucket = ts(&item) - ts(&item).rem_euclid(bucket_secs);
+        match groups.last_mut() {
+            Some((start, group)) if *start == bucket => group.push(item),
+            _ => groups.push((bucket, vec![item])),
+        }
+    }
+    groups
+}
+
+/// Tiered metrics history: raw samples for a short period plus minute and
+/// hour rollups for longer retention. Each tier is kept in memory and
+/// mirrored to an append-only JSON lines file.
+pub struct HistoryStore {
+    series: HashMap<String, Series>,
+    raw_retention: i64,
+    minute_retention: i64,
+    hour_retention: i64,
+    // Start of the first minute / hour that has not been rolled up yet.
+    minute_watermark: i64,
+    hour_watermark: i64,
+    transitions: HashMap<String, VecDeque<Transition>>,
+    files: Option<[TierFile; 3]>,
+    transitions_file: Option<TierFile>,
+    // Set by renames, so the lines under the old names are compacted away soon.
+    renamed: bool,
+}
+
+pub type SharedHistory = Arc<Mutex<HistoryStore>>;
+
+impl HistoryStore {
+    /// Creates the store and loads whatever is still within retention from disk.
+    pub fn open(settings: &HistoryConfig) -> Self {
+        let now = Utc::now().timestamp();
+        let mut store = Self {
+            series: HashMap::new(),
+            raw_retention: settings.raw_retention_hours.unwrap_or(1) as i64 * 3600,
+            minute_retention: settings.minute_retention_days.unwrap_or(7) as i64 * 86400,
+            hour_retention: settings.hour_retention_days.unwrap_or(365) as i64 * 86400,
+            minute_watermark: now - now.rem_euclid(60),
+            hour_watermark: now - now.rem_euclid(3600),
+            transitions: HashMap::new(),
+            files: None,
+            transitions_file: None,
+            renamed: false,
+        };
+        if !settings.enabled.unwrap_or(true) {
+            return store;
+        }
+
+        let dir = PathBuf::from(settings.dir.clone().unwrap_or_else(|| "history".into()));
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            println!("Failed to create history directory {:?}: {}", dir, e);
+            return store;
+        }
+        let files = [
+            TierFile::open(dir.join("raw.jsonl")),
+            TierFile::open(dir.join("1m.jsonl")),
+            TierFile::open(dir.join("1h.jsonl")),
+        ];
+
+        for line in files[0].lines() {
+            if let Ok(record) = serde_json::from_str::<Record>(&line) {
+                if record.ts >= now - store.raw_retention {
+                    for (name, value) in record.values {
+                        let raw = &mut store.series.entry(name).or_default().raw;
+                        insert_sorted(raw, Sample { ts: record.ts, value }, |s| s.ts, true);
+                    }
+                }
+            }
+        }
+        for (tier, file) in [(Tier::Minute, &files[1]), (Tier::Hour, &files[2])] {
+            for line in file.lines() {
+                if let Ok(entry) = serde_json::from_str::<RollupLine>(&line) {
+                    store.push_rollup(tier, entry.series, entry.rollup, now);
+                }
+            }
+        }
+
+        // Resume rolling up right after the newest rollup on disk.
+        let newest = |tier: Tier| store.series.values()
+            .filter_map(|s| match tier {
+                Tier::Hour => s.hour.back(),
+                _ => s.minute.back(),
+            })
+            .map(|r| r.ts + tier.bucket_secs())
+            .max();
+        let oldest_raw = store.series.values().filter_map(|s| s.raw.front()).map(|s| s.ts).min();
+        if let Some(ts) = newest(Tier::Minute).or(oldest_raw.map(|ts| ts - ts.rem_euclid(60))) {
+            store.minute_watermark = ts;
+        }
+        let oldest_minute = store.series.values().filter_map(|s| s.minute.front()).map(|r| r.ts).min();
+        if let Some(ts) = newest(Tier::Hour).or(oldest_minute.map(|ts| ts - ts.rem_euclid(3600))) {
+            store.hour_watermark = ts;
+        }
+
+        let transitions_file = TierFile::open(dir.join("transitions.jsonl"));
+        for line in transitions_file.lines() {
+            if let Ok(transition) = serde_json::from_str::<Transition>(&line) {
+                if transition.ts >= now - store.hour_retention {
+                    store.transitions.entry(transition.entity.clone()).or_default().push_back(transition);
+                }
+            }
+        }
+
+        store.files = Some(files);
+        store.transitions_file = Some(transitions_file);
+        store
+    }
+
+    fn push_rollup(&mut self, tier: Tier, name: String, rollup: Rollup, now: i64) {
+        let (retention, rollups) = match tier {
+            Tier::Hour => (self.hour_retention, &mut self.series.entry(name).or_default().hour),
+            _ => (self.minute_retention, &mut self.series.entry(name).or_default().minute),
+        };
+        // Backfills append out-of-order lines; later lines win.
+        if rollup.ts >= now - retention {
+            insert_sorted(rollups, rollup, |r| r.ts, true);
+        }
+    }
+
+    /// Records a set of series values taken at the same time.
+    pub fn record(&mut self, ts: i64, values: Vec<(String, f64)>) {
+        if let Some(files) = self.files.as_mut() {
+            files[0].append(&Record { ts, values: values.iter().cloned().collect() });
+            files[0].flush();
+        }
+        let cutoff = ts - self.raw_retention;
+        for (name, value) in values {
+            let raw = &mut self.series.entry(name).or_default().raw;
+            raw.push_back(Sample { ts, value });
+            while raw.front().map(|s| s.ts < cutoff).unwrap_or(false) {
+                raw.pop_front();
+            }
+        }
+    }
+
+    /// The timestamp of the newest data held for `series` in any tier.
+    pub fn latest_ts(&self, series: &str) -> Option<i64> {
+        let data = self.series.get(series)?;
+        [
+            data.raw.back().map(|s| s.ts),
+            data.minute.back().map(|r| r.ts + 59),
+            data.hour.back().map(|r| r.ts + 3599),
+        ].into_iter().flatten().max()
+    }
+
+    /// The newest raw sample of `series`.
+    pub fn latest_sample(&self, series: &str) -> Option<Sample> {
+        self.series.get(series)?.raw.back().copied()
+    }
+
+    /// The minute rollups of `series` whose bucket starts in `from..to`.
+    pub fn minute_rollups(&self, series: &str, from: i64, to: i64) -> Vec<Rollup> {
+        match self.series.get(series) {
+            Some(data) => data.minute.iter().filter(|r| r.ts >= from && r.ts < to).copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The hour rollups of `series` whose bucket starts in `from..to`.
+    pub fn hour_rollups(&self, series: &str, from: i64, to: i64) -> Vec<Rollup> {
+        match self.series.get(series) {
+            Some(data) => data.hour.iter().filter(|r| r.ts >= from && r.ts < to).copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Merges points fetched from elsewhere (a remote's own /api/history) into
+    /// `series`, filling gaps without overwriting data we recorded ourselves.
+    /// Rollups already computed for the affected minutes and hours are redone.
+    pub fn backfill(&mut self, series: &str, tier: Tier, points: &[Point]) {
+        let now = Utc::now().timestamp();
+        let mut raw_lines = Vec::new();
+        let mut minute_lines = Vec::new();
+        let mut hour_lines = Vec::new();
+        let mut touched_minutes = BTreeSet::new();
+        let mut touched_hours = BTreeSet::new();
+        let data = self.series.entry(series.to_string()).or_default();
+
+        for point in points {
+            match tier {
+                Tier::Raw => {
+                    let sample = Sample { ts: point.ts, value: point.value };
+                    if point.ts >= now - self.raw_retention && insert_sorted(&mut data.raw, sample, |s| s.ts, false) {
+                        raw_lines.push(Record { ts: point.ts, values: HashMap::from([(series.to_string(), point.value)]) });
+                        touched_minutes.insert(point.ts - point.ts.rem_euclid(60));
+                    }
+                },
+                Tier::Minute => {
+                    let rollup = point.to_rollup();
+                    if point.ts >= now - self.minute_retention && insert_sorted(&mut data.minute, rollup, |r| r.ts, false) {
+                        minute_lines.push(RollupLine { series: series.to_string(), rollup });
+                        touched_hours.insert(point.ts - point.ts.rem_euclid(3600));
+                    }
+                },
+                Tier::Hour => {
+                    let rollup = point.to_rollup();
+                    if point.ts >= now - self.hour_retention && insert_sorted(&mut data.hour, rollup, |r| r.ts, false) {
+                        hour_lines.push(RollupLine { series: series.to_string(), rollup });
+                    }
+                },
+            }
+        }
+
+        for minute in touched_minutes.into_iter().filter(|m| *m < self.minute_watermark) {
+            let samples: Vec<Sample> = data.raw.iter().filter(|s| s.ts >= minute && s.ts < minute + 60).copied().collect();
+            let rollup = rollup_samples(minute, &samples);
+            insert_sorted(&mut data.minute, rollup, |r| r.ts, true);
+            minute_lines.push(RollupLine { series: series.to_string(), rollup });
+            touched_hours.insert(minute - minute.rem_euclid(3600));
+        }
+        for hour in touched_hours.into_iter().filter(|h| *h < self.hour_watermark) {
+            let minutes: Vec<Rollup> = data.minute.iter().filter(|r| r.ts >= hour && r.ts < hour + 3600).copied().collect();
+            if !minutes.is_empty() {
+                let rollup = merge_rollups(hour, &minutes);
+                insert_sorted(&mut data.hour, rollup, |r| r.ts, true);
+                hour_lines.push(RollupLine { series: series.to_string(), rollup });
+            }
+        }
+
+        if let Some(files) = self.files.as_mut() {
+            raw_lines.iter().for_each(|line| files[0].append(line));
+            minute_lines.iter().for_each(|line| files[1].append(line));
+            hour_lines.iter().for_each(|line| files[2].append(line));
+            files.iter_mut().for_each(TierFile::flush);
+        }
+    }
+
+    /// Records the online state of `entity`, storing a transition when it changed.
+    pub fn record_state(&mut self, entity: &str, ts: i64, online: bool) {
+        let transitions = self.transitions.entry(entity.to_string()).or_default();
+        if transitions.back().map(|t| t.online == online).unwrap_or(false) {
+            return;
+        }
+        let transition = Transition { entity: entity.to_string(), ts, online };
+        if let Some(file) = self.transitions_file.as_mut() {
+            file.append(&transition);
+            file.flush();
+        }
+        transitions.push_back(transition);
+    }
+
+    /// Moves series `from` to `to`, merging it into data already held there
+    /// (which wins). The moved data is appended to the files under its new
+    /// name; the lines under the old name go at the next compaction.
+    pub fn rename_series(&mut self, from: &str, to: &str) {
+        let Some(old) = self.series.remove(from) else {
+            return;
+        };
+        self.renamed = true;
+        let data = self.series.entry(to.to_string()).or_default();
+        let mut raw_lines: BTreeMap<i64, HashMap<String, f64>> = BTreeMap::new();
+        for sample in old.raw {
+            if insert_sorted(&mut data.raw, sample, |s| s.ts, false) {
+                raw_lines.entry(sample.ts).or_default().insert(to.to_string(), sample.value);
+            }
+        }
+        let mut rollup_lines: [Vec<RollupLine>; 2] = Default::default();
+        for (lines, (old, new)) in rollup_lines.iter_mut().zip([(old.minute, &mut data.minute), (old.hour, &mut data.hour)]) {
+            for rollup in old {
+                if insert_sorted(new, rollup, |r| r.ts, false) {
+                    lines.push(RollupLine { series: to.to_string(), rollup });
+                }
+            }
+        }
+        if let Some(files) = self.files.as_mut() {
+            raw_lines.into_iter().for_each(|(ts, values)| files[0].append(&Record { ts, values }));
+            rollup_lines[0].iter().for_each(|line| files[1].append(line));
+            rollup_lines[1].iter().for_each(|line| files[2].append(line));
+            files.iter_mut().for_each(TierFile::flush);
+        }
+    }
+
+    /// Moves the recorded transitions of `from` to `to`, like `rename_series`.
+    pub fn rename_entity(&mut self, from: &str, to: &str) {
+        let Some(old) = self.transitions.remove(from) else {
+            return;
+        };
+        self.renamed = true;
+        let transitions = self.transitions.entry(to.to_string()).or_default();
+        let mut moved = Vec::new();
+        for transition in old {
+            let transition = Transition { entity: to.to_string(), ..transition };
+            if insert_sorted(transitions, transition.clone(), |t| t.ts, false) {
+                moved.push(transition);
+            }
+        }
+        if let Some(file) = self.transitions_file.as_mut() {
+            moved.iter().for_each(|transition| file.append(transition));
+            file.flush();
+        }
+    }
+
+    /// Computes how long `entity` was online between `from` and `to`.
+    pub fn availability(&self, entity: &str, from: i64, to: i64) -> Option<Availability> {
+        let transitions = self.transitions.get(entity)?;
+        let mut state = transitions.iter().rev().find(|t| t.ts <= from).map(|t| t.online);
+        let mut cursor = from;
+        let (mut online_secs, mut offline_secs, mut unknown_secs) = (0, 0, 0);
+        let mut add_span = |state: Option<bool>, secs: i64| match state {
+            Some(true) => online_secs += secs,
+            Some(false) => offline_secs += secs,
+            None => unknown_secs += secs,
+        };
+
+        let in_window: Vec<Transition> = transitions.iter()
+            .filter(|t| t.ts > from && t.ts <= to)
+            .cloned()
+            .collect();
+        for transition in in_window.iter() {
+            add_span(state, transition.ts - cursor);
+            cursor = transition.ts;
+            state = Some(transition.online);
+        }
+        add_span(state, to - cursor);
+
+        let known = online_secs + offline_secs;
+        Some(Availability {
+            from,
+            to,
+            online_secs,
+            offline_secs,
+            unknown_secs,
+            uptime_percent: if known > 0 { Some(online_secs as f64 * 100.0 / known as f64) } else { None },
+            transitions: in_window,
+        })
+    }
+
+    /// Names of all series that currently hold data.
+    pub fn series_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.series.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Picks the finest tier that still covers `from` and is not finer than `step`.
+    fn choose_tier(&self, from: i64, step: Option<i64>, now: i64) -> Tier {
+        let step = step.unwrap_or(0);
+        if from >= now - self.raw_retention && step < Tier::Minute.bucket_secs() {
+            Tier::Raw
+        } else if from >= now - self.minute_retention && step < Tier::Hour.bucket_secs() {
+            Tier::Minute
+        } else {
+            Tier::Hour
+        }
+    }
+
+    /// Returns points of `series` between `from` and `to` (inclusive), read from the
+    /// tier matching the range and step. With a `step` coarser than the tier,
+    /// points are merged into buckets of that many seconds.
+    pub fn query(&self, series: &str, from: i64, to: i64, step: Option<i64>) -> Option<(Tier, Vec<Point>)> {
+        let data = self.series.get(series)?;
+        let tier = self.choose_tier(from, step, Utc::now().timestamp());
+        let rollups: Vec<Rollup> = match tier {
+            Tier::Raw => data.raw.iter().filter(|s| s.ts >= from && s.ts <= to)
+                .map(|s| Rollup { ts: s.ts, min: s.value, avg: s.value, max: s.value, p95: s.value, count: 1 })
+                .collect(),
+            Tier::Minute => data.minute.iter().filter(|r| r.ts >= from && r.ts <= to).copied().collect(),
+            Tier::Hour => data.hour.iter().filter(|r| r.ts >= from && r.ts <= to).copied().collect(),
+        };
+
+        let rollups = match step {
+            Some(step) if step > tier.bucket_secs() => group_by_bucket(rollups.into_iter(), |r| r.ts, step)
+                .into_iter()
+                .map(|(ts, group)| merge_rollups(ts, &group))
+                .collect(),
+            _ => rollups,
+        };
+
+        let points = rollups.into_iter().map(|r| {
+            let detail = |v: f64| if tier == Tier::Raw && step.unwrap_or(0) <= 1 { None } else { Some(v) };
+            Point {
+                ts: r.ts,
+                value: r.avg,
+                min: detail(r.min),
+                max: detail(r.max),
+                p95: detail(r.p95),
+                count: detail(r.count as f64).map(|c| c as u64),
+            }
+        }).collect();
+        Some((tier, points))
+    }
+
+    /// Rolls completed minutes of raw samples into the minute tier and completed
+    /// hours of minute rollups into the hour tier, then prunes expired data.
+    fn roll_up(&mut self, now: i64) {
+        let minute_end = now - now.rem_euclid(60);
+        let hour_end = now - now.rem_euclid(3600);
+        let (minute_start, hour_start) = (self.minute_watermark, self.hour_watermark);
+        let mut minute_lines = Vec::new();
+        let mut hour_lines = Vec::new();
+
+        for (name, series) in self.series.iter_mut() {
+            let samples = series.raw.iter().filter(|s| s.ts >= minute_start && s.ts < minute_end).copied();
+            for (ts, group) in group_by_bucket(samples, |s| s.ts, 60) {
+                let rollup = rollup_samples(ts, &group);
+                series.minute.push_back(rollup);
+                minute_lines.push(RollupLine { series: name.clone(), rollup });
+            }
+
+            let minutes = series.minute.iter().filter(|r| r.ts >= hour_start && r.ts < hour_end).copied();
+            for (ts, group) in group_by_bucket(minutes, |r| r.ts, 3600) {
+                let rollup = merge_rollups(ts, &group);
+                series.hour.push_back(rollup);
+                hour_lines.push(RollupLine { series: name.clone(), rollup });
+            }
+
+            while series.raw.front().map(|s| s.ts < now - self.raw_retention).unwrap_or(false) {
+                series.raw.pop_front();
+            }
+            while series.minute.front().map(|r| r.ts < now - self.minute_retention).unwrap_or(false) {
+                series.minute.pop_front();
+            }
+            while series.hour.front().map(|r| r.ts < now - self.hour_retention).unwrap_or(false) {
+                series.hour.pop_front();
+            }
+        }
+        self.series.retain(|_, s| !(s.raw.is_empty() && s.minute.is_empty() && s.hour.is_empty()));
+        for transitions in self.transitions.values_mut() {
+            // Keep the last transition before the cutoff so the state at the cutoff is known.
+            while transitions.len() > 1 && transitions[1].ts < now - self.hour_retention {
+                transitions.pop_front();
+            }
+        }
+        self.minute_watermark = self.minute_watermark.max(minute_end);
+        self.hour_watermark = self.hour_watermark.max(hour_end);
+
+        if let Some(files) = self.files.as_mut() {
+            for line in minute_lines.iter() {
+                files[1].append(line);
+            }
+            for line in hour_lines.iter() {
+                files[2].append(line);
+            }
+            files[1].flush();
+            files[2].flush();
+        }
+    }
+
+    /// Starts rewriting the history files without data older than each tier's
+    /// retention or of series no longer held (renamed ones). The filtering
+    /// (`Compaction::filter`) is left to the caller, to run without the lock;
+    /// `finish_compaction` swaps the results in.
+    fn start_compaction(&mut self, now: i64) -> Vec<(usize, Compaction, KeepLine)> {
+        let mut compactions: Vec<(usize, Compaction, KeepLine)> = Vec::new();
+        let cutoffs = [now - self.raw_retention, now - self.minute_retention, now - self.hour_retention];
+        let live: Arc<HashSet<String>> = Arc::new(self.series.keys().cloned().collect());
+        if let Some(files) = self.files.as_mut() {
+            let raw_live = Arc::clone(&live);
+            compactions.push((0, files[0].start_compaction(), Box::new(move |line: &str| {
+                serde_json::from_str::<Record>(line)
+                    .map(|r| r.ts >= cutoffs[0] && r.values.keys().any(|name| raw_live.contains(name)))
+                    .unwrap_or(false)
+            })));
+            for tier in [1, 2] {
+                let live = Arc::clone(&live);
+                compactions.push((tier, files[tier].start_compaction(), Box::new(move |line: &str| {
+                    serde_json::from_str::<RollupLine>(line)
+                        .map(|r| r.rollup.ts >= cutoffs[tier] && live.contains(&r.series))
+                        .unwrap_or(false)
+                })));
+            }
+        }
+        if let Some(file) = self.transitions_file.as_mut() {
+            let kept: HashSet<String> = self.transitions.values()
+                .flatten()
+                .filter_map(|t| serde_json::to_string(t).ok())
+                .collect();
+            compactions.push((TRANSITIONS_FILE, file.start_compaction(), Box::new(move |line: &str| kept.contains(line))));
+        }
+        compactions
+    }
+
+    fn finish_compaction(&mut self, compactions: &[(usize, Compaction)]) -> std::io::Result<()> {
+        for (index, compaction) in compactions {
+            let file = match *index {
+                TRANSITIONS_FILE => self.transitions_file.as_mut(),
+                tier => self.files.as_mut().map(|files| &mut files[tier]),
+            };
+            if let Some(file) = file {
+                file.finish_compaction(compaction)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Filters the history files off the async runtime and without the lock, which
+/// the poller and request handlers need meanwhile, then swaps them in.
+async fn compact(history: &SharedHistory, now: i64) -> std::io::Result<()> {
+    let compactions = history.lock().unwrap().start_compaction(now);
+    let filtered = tokio::task::spawn_blocking(move || {
+        compactions.into_iter()
+            .map(|(index, compaction, keep)| compaction.filter(keep).map(|_| (index, compaction)))
+            .collect::<std::io::Result<Vec<_>>>()
+    }).await.map_err(std::io::Error::other)??;
+    history.lock().unwrap().finish_compaction(&filtered)
+}
+
+/// Background task computing rollups every minute and compacting the
+/// history files every hour, or at the next minute after a rename.
+pub async fn maintain_history(
+    history: SharedHistory,
+    mut shutdown: watch::Receiver<bool>
+) {
+    let mut last_compaction = Utc::now().timestamp();
+    loop {
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down history maintenance.");
+                    break;
+                }
+            },
+            _ = sleep(Duration::from_secs(60)) => {
+                let now = Utc::now().timestamp();
+                let renamed = {
+                    let mut history = history.lock().unwrap();
+                    history.roll_up(now);
+                    std::mem::take(&mut history.renamed)
+                };
+                if renamed || now - last_compaction >= 3600 {
+                    last_compaction = now;
+                    if let Err(e) = compact(&history, now).await {
+                        println!("Failed to compact history files: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_store() -> HistoryStore {
+        HistoryStore::open(&HistoryConfig { enabled: Some(false), ..Default::default() })
+    }
+
+    fn rollup(ts: i64, min: f64, avg: f64, max: f64, count: u64) -> Rollup {
+        Rollup { ts, min, avg, max, p95: max, count }
+    }
+
+    #[test]
+    fn percentile_handles_empty_single_and_unsorted_input() {
+        assert_eq!(percentile(&mut [], 0.95), 0.0);
+        assert_eq!(percentile(&mut [4.0], 0.95), 4.0);
+        assert_eq!(percentile(&mut [4.0], 0.0), 4.0);
+        let mut values: Vec<f64> = (1..=100).rev().map(|v| v as f64).collect();
+        assert_eq!(percentile(&mut values, 0.95), 95.0);
+        assert_eq!(percentile(&mut values, 1.0), 100.0);
+    }
+
+    #[test]
+    fn rollup_of_a_single_sample() {
+        let rollup = rollup_samples(60, &[Sample { ts: 61, value: 3.0 }]);
+        assert_eq!((rollup.min, rollup.avg, rollup.max, rollup.p95, rollup.count), (3.0, 3.0, 3.0, 3.0, 1));
+    }
+
+    #[test]
+    fn merge_weights_averages_by_count() {
+        let merged = merge_rollups(0, &[rollup(0, 1.0, 2.0, 3.0, 1), rollup(60, 0.0, 5.0, 9.0, 3)]);
+        assert_eq!(merged.count, 4);
+        assert_eq!(merged.avg, (2.0 + 15.0) / 4.0);
+        assert_eq!((merged.min, merged.max), (0.0, 9.0));
+
+        let empty = merge_rollups(0, &[]);
+        assert_eq!((empty.count, empty.avg), (0, 0.0));
+    }
+
+    #[test]
+    fn chooses_finest_tier_covering_the_range() {
+        let store = memory_store();
+        let now = 10_000_000;
+        assert_eq!(store.choose_tier(now - 3600, None, now), Tier::Raw);
+        assert_eq!(store.choose_tier(now - 3601, None, now), Tier::Minute);
+        assert_eq!(store.choose_tier(now - 60, Some(59), now), Tier::Raw);
+        assert_eq!(store.choose_tier(now - 60, Some(60), now), Tier::Minute);
+        assert_eq!(store.choose_tier(now - 7 * 86400, None, now), Tier::Minute);
+        assert_eq!(store.choose_tier(now - 7 * 86400 - 1, None, now), Tier::Hour);
+        assert_eq!(store.choose_tier(now - 60, Some(3600), now), Tier::Hour);
+    }
+
+    #[test]
+    fn query_merges_raw_samples_into_steps() {
+        let mut store = memory_store();
+        let now = Utc::now().timestamp();
+        let start = now - now.rem_euclid(60) - 60;
+        for i in 0..10 {
+            store.record(start + i, vec![("cpu".into(), i as f64)]);
+        }
+        assert!(store.query("missing", start, now, None).is_none());
+
+        let (tier, points) = store.query("cpu", start, start + 9, None).unwrap();
+        assert_eq!(tier, Tier::Raw);
+        assert_eq!(points.len(), 10);
+        assert!(points[0].min.is_none());
+
+        let (_, points) = store.query("cpu", start, start + 9, Some(5)).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].ts, points[0].value, points[0].count), (start, 2.0, Some(5)));
+        assert_eq!((points[1].min, points[1].max), (Some(5.0), Some(9.0)));
+    }
+
+    #[test]
+    fn rolls_complete_minutes_up() {
+        let mut store = memory_store();
+        let now = Utc::now().timestamp();
+        let start = now - now.rem_euclid(60) - 180;
+        store.minute_watermark = start;
+        for i in 0..120 {
+            store.record(start + i, vec![("cpu".into(), (i / 60) as f64)]);
+        }
+        store.roll_up(now);
+        let minutes = store.minute_rollups("cpu", start, now);
+        assert_eq!(minutes.len(), 2);
+        assert_eq!((minutes[0].ts, minutes[0].avg, minutes[0].count), (start, 0.0, 60));
+        assert_eq!((minutes[1].ts, minutes[1].avg), (start + 60, 1.0));
+
+        // Rolling up again does not duplicate the minutes.
+        store.roll_up(now);
+        assert_eq!(store.minute_rollups("cpu", start, now).len(), 2);
+    }
+
+    #[test]
+    fn availability_counts_unknown_time_before_the_first_transition() {
+        let mut store = memory_store();
+        store.record_state("remote", 100, true);
+        store.record_state("remote", 150, true);
+        store.record_state("remote", 200, false);
+        let availability = store.availability("remote", 0, 300).unwrap();
+        assert_eq!((availability.unknown_secs, availability.online_secs, availability.offline_secs), (100, 100, 100));
+        assert_eq!(availability.uptime_percent, Some(50.0));
+        assert_eq!(availability.transitions.len(), 2);
+        assert!(store.availability("other", 0, 300).is_none());
+    }
+
+    #[test]
+    fn renaming_merges_into_existing_data() {
+        let mut store = memory_store();
+        let now = Utc::now().timestamp();
+        store.record(now - 2, vec![("remote:a:cpu".into(), 1.0)]);
+        store.record(now - 1, vec![("remote:a:cpu".into(), 2.0), ("remote:b:cpu".into(), 9.0)]);
+        store.rename_series("remote:a:cpu", "remote:b:cpu");
+        store.rename_series("missing", "remote:b:cpu");
+        assert_eq!(store.series_names(), vec!["remote:b:cpu".to_string()]);
+        let (_, points) = store.query("remote:b:cpu", now - 10, now, None).unwrap();
+        // The value already held under the new name wins.
+        assert_eq!(points.iter().map(|p| p.value).collect::<Vec<_>>(), vec![1.0, 9.0]);
+
+        store.record_state("10.0.0.9:6989", 100, false);
+        store.record_state("10.0.0.9:6989", 200, true);
+        store.rename_entity("10.0.0.9:6989", "pylon-id");
+        assert!(store.availability("10.0.0.9:6989", 0, 300).is_none());
+        let availability = store.availability("pylon-id", 0, 300).unwrap();
+        assert_eq!((availability.offline_secs, availability.online_secs), (100, 100));
+        assert!(availability.transitions.iter().all(|t| t.entity == "pylon-id"));
+    }
+
+    #[test]
+    fn renamed_history_is_compacted_under_the_new_names() {
+        let dir = std::env::temp_dir().join(format!("pylon-history-rename-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let settings = HistoryConfig { dir: Some(dir.to_string_lossy().into()), ..Default::default() };
+        let now = Utc::now().timestamp();
+        let mut store = HistoryStore::open(&settings);
+        store.record(now - 1, vec![("remote:old:cpu".into(), 5.0)]);
+        store.record_state("old", now - 1, true);
+        assert!(!store.renamed);
+        store.rename_series("remote:old:cpu", "remote:new:cpu");
+        store.rename_entity("old", "new");
+        assert!(store.renamed);
+
+        let compactions = store.start_compaction(now);
+        let filtered: Vec<(usize, Compaction)> = compactions.into_iter()
+            .map(|(index, compaction, keep)| compaction.filter(keep).map(|_| (index, compaction)).unwrap())
+            .collect();
+        store.finish_compaction(&filtered).unwrap();
+        let raw = std::fs::read_to_string(dir.join("raw.jsonl")).unwrap();
+        let transitions = std::fs::read_to_string(dir.join("transitions.jsonl")).unwrap();
+        assert!(!raw.contains("remote:old:cpu") && raw.contains("remote:new:cpu"));
+        assert!(!transitions.contains("\"old\"") && transitions.contains("\"new\""));
+
+        let store = HistoryStore::open(&settings);
+        assert_eq!(store.series_names(), vec!["remote:new:cpu".to_string()]);
+        assert!(store.availability("old", now - 10, now).is_none());
+        assert!(store.availability("new", now - 10, now).is_some());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compaction_keeps_lines_appended_while_filtering() {
+        let dir = std::env::temp_dir().join(format!("pylon-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut store = HistoryStore::open(&HistoryConfig { dir: Some(dir.to_string_lossy().into()), ..Default::default() });
+        let now = Utc::now().timestamp();
+        store.record(now - 7200, vec![("cpu".into(), 1.0)]);
+        store.record(now, vec![("cpu".into(), 2.0)]);
+
+        let compactions = store.start_compaction(now);
+        store.record(now + 1, vec![("cpu".into(), 3.0)]);
+        let filtered: Vec<(usize, Compaction)> = compactions.into_iter()
+            .map(|(index, compaction, keep)| compaction.filter(keep).map(|_| (index, compaction)).unwrap())
+            .collect();
+        store.finish_compaction(&filtered).unwrap();
+        store.record(now + 2, vec![("cpu".into(), 4.0)]);
+
+        let kept: Vec<i64> = std::fs::read_to_string(dir.join("raw.jsonl")).unwrap().lines()
+            .map(|line| serde_json::from_str::<Record>(line).unwrap().ts)
+            .collect();
+        assert_eq!(kept, vec![now, now + 1, now + 2]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
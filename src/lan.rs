@@ -0,0 +1,518 @@
+// src/lan.rs
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tokio::time::{interval, sleep, Duration};
+use crate::config_manager::{save_config, Config, RemotePylonConfig};
+use crate::pairing::SharedPairing;
+use crate::server::PYLON_VERSION;
+
+const DEFAULT_LAN_PORT: u16 = 6990;
+const DEFAULT_BROADCAST: &str = "255.255.255.255";
+const BEACON_INTERVAL: u64 = 10;
+/// Pylons whose beacon has not been heard for this long are forgotten.
+const FOUND_TTL_SECS: i64 = 35;
+
+/// Announcement broadcast by every pylon with `lan_discovery` enabled.
+#[derive(Debug, Serialize, Deserialize)]
+struct Beacon {
+    // Always "_pylon._tcp", so unrelated traffic on the port is ignored.
+    service: String,
+    // The pylon id.
+    id: String,
+    name: Option<String>,
+    location: Option<String>,
+    version: String,
+    // Port of the pylon's web server.
+    port: u16,
+}
+
+/// A pylon heard on the local network.
+#[derive(Debug, Serialize, Clone)]
+pub struct LanPylon {
+    pub id: String,
+    pub ip: String,
+    pub port: u16,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub version: String,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Pylons heard on the local network, by id.
+pub type SharedLan = Arc<Mutex<HashMap<String, LanPylon>>>;
+
+/// Settings of the beacon, compared on every tick to notice config changes.
+#[derive(PartialEq)]
+struct LanSettings {
+    port: u16,
+    broadcast: String,
+}
+
+fn lan_settings(config: &Config) -> Option<LanSettings> {
+    config.lan_discovery.unwrap_or(false).then(|| LanSettings {
+        port: config.lan_port.unwrap_or(DEFAULT_LAN_PORT),
+        broadcast: config.lan_broadcast.clone().unwrap_or_else(|| DEFAULT_BROADCAST.into()),
+    })
+}
+
+/// Binds the beacon port so several pylons on one host can share it.
+fn bind(port: u16) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// The beacon announcing this pylon, whose server is bound to `port`.
+fn beacon(config: &Config, port: u16) -> Beacon {
+    Beacon {
+        service: "_pylon._tcp".into(),
+        id: config.id.clone().unwrap_or_default(),
+        name: config.name.clone(),
+        location: config.location.clone(),
+        version: PYLON_VERSION.into(),
+        port,
+    }
+}
+
+/// Announces this pylon on the local network every 10 seconds with a UDP
+/// broadcast beacon and listens for the beacons of other pylons, which the
+/// admin page offers for adoption. Does nothing unless `lan_discovery` is on.
+/// `port` is the port the server is bound to, which may differ from `local_port`.
+pub async fn run_lan_discovery(config_arc: Arc<RwLock<Config>>, found: SharedLan, port: u16, mut shutdown: watch::Receiver<bool>) {
+    loop {
+        let settings = lan_settings(&config_arc.read().unwrap());
+        let socket = match settings.as_ref().map(|settings| bind(settings.port)) {
+            Some(Ok(socket)) => Some(socket),
+            Some(Err(e)) => {
+                println!("Failed to bind LAN discovery port: {}", e);
+                None
+            },
+            None => None,
+        };
+
+        if let (Some(socket), Some(settings)) = (socket, settings) {
+            let mut ticker = interval(Duration::from_secs(BEACON_INTERVAL));
+            let mut buf = [0u8; 2048];
+            loop {
+                tokio::select! {
+                    _ = shutdown.changed() => {
+                        if *shutdown.borrow() {
+                            println!("Shutting down LAN discovery.");
+                            return;
+                        }
+                    },
+                    received = socket.recv_from(&mut buf) => {
+                        if let Ok((len, from)) = received {
+                            let own_id = config_arc.read().unwrap().id.clone().unwrap_or_default();
+                            receive(&found, &buf[..len], from, &own_id);
+                        }
+                    },
+                    _ = ticker.tick() => {
+                        let (current, beacon) = {
+                            let config = config_arc.read().unwrap();
+                            (lan_settings(&config), beacon(&config, port))
+                        };
+                        if current.as_ref() != Some(&settings) {
+                            // Disabled or moved: rebind with the new settings.
+                            break;
+                        }
+                        let payload = serde_json::to_vec(&beacon).unwrap_or_default();
+                        if let Err(e) = socket.send_to(&payload, (settings.broadcast.as_str(), settings.port)).await {
+                            println!("Failed to send LAN beacon: {}", e);
+                        }
+                        let now = Utc::now();
+                        found.lock().unwrap().retain(|_, pylon| (now - pylon.last_seen).num_seconds() < FOUND_TTL_SECS);
+                    }
+                }
+            }
+            continue;
+        }
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down LAN discovery.");
+                    return;
+                }
+            },
+            _ = sleep(Duration::from_secs(BEACON_INTERVAL)) => {}
+        }
+    }
+}
+
+fn receive(found: &SharedLan, payload: &[u8], from: SocketAddr, own_id: &str) {
+    let Ok(beacon) = serde_json::from_slice::<Beacon>(payload) else {
+        return;
+    };
+    if beacon.service != "_pylon._tcp" || beacon.id.is_empty() || beacon.id == own_id {
+        return;
+    }
+    let mut found = found.lock().unwrap();
+    if !found.contains_key(&beacon.id) {
+        println!("Found pylon {} on the local network at {}:{}", beacon.name.as_deref().unwrap_or(&beacon.id), from.ip(), beacon.port);
+    }
+    found.insert(beacon.id.clone(), LanPylon {
+        id: beacon.id,
+        ip: from.ip().to_string(),
+        port: beacon.port,
+        name: beacon.name,
+        location: beacon.location,
+        version: beacon.version,
+        last_seen: Utc::now(),
+    });
+}
+
+/// Whether `remote` is the pylon heard at `ip:port` with pylon id `id`.
+fn same_pylon(remote: &RemotePylonConfig, id: &str, ip: &str, port: u16) -> bool {
+    remote.id.as_deref() == Some(id) || (remote.ip == ip && remote.port == port)
+}
+
+/// Pylons heard on the local network that are not in `remote_pylons` yet,
+/// under any address.
+pub fn unconfigured(config: &Config, found: &SharedLan) -> Vec<LanPylon> {
+    let mut pylons: Vec<LanPylon> = found.lock().unwrap().values()
+        .filter(|pylon| !config.remote_pylons.iter().flatten().any(|r| same_pylon(r, &pylon.id, &pylon.ip, pylon.port)))
+        .cloned()
+        .collect();
+    pylons.sort_by(|a, b| (&a.ip, a.port).cmp(&(&b.ip, b.port)));
+    pylons
+}
+
+/// Body of POST /api/lan/adopt: the adopting pylon introduces itself.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdoptRequest {
+    pub secret: String,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub port: u16,
+    // A credential the adopter issued, so the adopted pylon can poll it back.
+    pub token: String,
+    // The adopter's pylon id.
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+/// Reply to an adoption: the credential to poll the adopted pylon with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdoptResponse {
+    pub token: String,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Adopts a pylon heard on the network: exchanges pairing credentials with
+/// it (both sides must share the same `lan_secret`), or uses `token` when
+/// given, and adds it to `remote_pylons`. `port` is the port our server is
+/// bound to, which the adopted pylon polls us back on.
+pub async fn adopt(config_arc: &Arc<RwLock<Config>>, pairing: &SharedPairing, found: &SharedLan, id: &str, token: Option<String>, port: u16) -> Result<RemotePylonConfig, String> {
+    let pylon = found.lock().unwrap().get(id).cloned().ok_or_else(|| format!("no pylon '{}' on the local network", id))?;
+    let mut remote = RemotePylonConfig {
+        ip: pylon.ip.clone(),
+        port: pylon.port,
+        token: String::new(),
+        id: Some(pylon.id.clone()),
+        name: pylon.name.clone(),
+        location: pylon.location.clone(),
+        description: None,
+        poll_interval: None,
+        timeout: None,
+        retry_backoff: None,
+        discovered: None,
+        last_seen: None,
+    };
+    // The credential we issued the adopted pylon, if we exchanged any.
+    let mut ours = None;
+    match token {
+        Some(token) => remote.token = token,
+        None => {
+            let secret = config_arc.read().unwrap().lan_secret.clone().filter(|secret| !secret.is_empty())
+                .ok_or("set the same lan_secret on both pylons, or enter the pylon's token")?;
+            let credential = pairing.lock().unwrap()
+                .issue(pylon.name.clone(), format!("{}:{}", pylon.ip, pylon.port), Some(pylon.id.clone()));
+            let request = {
+                let config = config_arc.read().unwrap();
+                AdoptRequest {
+                    secret,
+                    name: config.name.clone(),
+                    location: config.location.clone(),
+                    description: config.description.clone(),
+                    port,
+                    token: credential.token.clone(),
+                    id: config.id.clone(),
+                }
+            };
+            ours = Some(credential.id.clone());
+            let url = format!("http://{}:{}/api/lan/adopt", pylon.ip, pylon.port);
+            let result = async {
+                let resp = Client::new().post(&url)
+                    .json(&request)
+                    .timeout(Duration::from_secs(10))
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if !resp.status().is_success() {
+                    let status = resp.status();
+                    let error = resp.json::<serde_json::Value>().await.ok()
+                        .and_then(|body| body["error"].as_str().map(String::from))
+                        .unwrap_or_default();
+                    return Err(format!("HTTP {} {}", status, error));
+                }
+                resp.json::<AdoptResponse>().await.map_err(|e| e.to_string())
+            }.await;
+            let adopted = match result {
+                Ok(adopted) => adopted,
+                Err(e) => {
+                    pairing.lock().unwrap().discard(&credential.id);
+                    return Err(e);
+                }
+            };
+            remote.token = adopted.token;
+            remote.name = adopted.name.or(remote.name);
+            remote.location = adopted.location.or(remote.location);
+            remote.description = adopted.description;
+        }
+    }
+
+    let mut config = config_arc.write().unwrap();
+    if config.remote_pylons.iter().flatten().any(|r| same_pylon(r, &pylon.id, &remote.ip, remote.port)) {
+        drop(config);
+        if let Some(ours) = ours {
+            pairing.lock().unwrap().discard(&ours);
+        }
+        return Err(format!("{}:{} is already configured", remote.ip, remote.port));
+    }
+    config.remote_pylons.get_or_insert_with(Vec::new).push(remote.clone());
+    save_config(&config).map_err(|e| e.to_string())?;
+    println!("Adopted pylon {}:{} from the local network", remote.ip, remote.port);
+    Ok(remote)
+}
+
+/// Answers an adoption request when it carries our `lan_secret`: issues the
+/// adopter a pairing credential and offers it as a peer (see discovery.rs),
+/// so it can be polled back once approved. Repeated wrong secrets lock
+/// adoption for a while (see pairing.rs).
+pub fn answer_adoption(config_arc: &Arc<RwLock<Config>>, pairing: &SharedPairing, discovery: &crate::discovery::SharedDiscovery, request: AdoptRequest, ip: String) -> Result<AdoptResponse, String> {
+    let secret_matches = {
+        let config = config_arc.read().unwrap();
+        let secret = config.lan_secret.as_deref().filter(|secret| !secret.is_empty());
+        if !config.lan_discovery.unwrap_or(false) || secret.is_none() {
+            return Err("adoption is disabled on this pylon".into());
+        }
+        secret == Some(request.secret.as_str())
+    };
+    let credential = {
+        let mut pairing = pairing.lock().unwrap();
+        pairing.check_adoption(secret_matches)?;
+        pairing.issue(request.name.clone(), format!("{}:{}", ip, request.port), request.id.clone())
+    };
+    let response = {
+        let config = config_arc.read().unwrap();
+        AdoptResponse {
+            token: credential.token,
+            name: config.name.clone(),
+            location: config.location.clone(),
+            description: config.description.clone(),
+        }
+    };
+    let adopter = RemotePylonConfig {
+        ip,
+        port: request.port,
+        token: request.token,
+        id: request.id,
+        name: request.name,
+        location: request.location,
+        description: request.description,
+        poll_interval: None,
+        timeout: None,
+        retry_backoff: None,
+        discovered: None,
+        last_seen: None,
+    };
+    println!("Adopted by {}:{} over the local network", adopter.ip, adopter.port);
+    discovery.lock().unwrap().advertise(config_arc, adopter, "lan");
+    Ok(response)
+}
+
+/// Summary for the admin page.
+pub fn status(config: &Config, found: &SharedLan) -> serde_json::Value {
+    json!({
+        "enabled": config.lan_discovery.unwrap_or(false),
+        "adoption": config.lan_secret.as_deref().is_some_and(|secret| !secret.is_empty()),
+        "pylons": unconfigured(config, found),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn beacon_from(id: &str, port: u16) -> Vec<u8> {
+        let config = Config { id: Some(id.into()), name: Some(format!("pylon {}", id)), ..Default::default() };
+        serde_json::to_vec(&beacon(&config, port)).unwrap()
+    }
+
+    #[test]
+    fn beacon_announces_the_bound_port() {
+        let config = Config { local_port: Some(6989), ..Default::default() };
+        assert_eq!(beacon(&config, 6991).port, 6991);
+    }
+
+    #[test]
+    fn ignores_own_foreign_and_malformed_beacons() {
+        let found = SharedLan::default();
+        let from: SocketAddr = "192.168.1.20:6990".parse().unwrap();
+        receive(&found, &beacon_from("self", 6989), from, "self");
+        receive(&found, b"not json", from, "self");
+        receive(&found, br#"{"service":"_http._tcp","id":"x","version":"1","port":80}"#, from, "self");
+        receive(&found, &beacon_from("", 6989), from, "self");
+        assert!(found.lock().unwrap().is_empty());
+
+        receive(&found, &beacon_from("other", 7000), from, "self");
+        let found = found.lock().unwrap();
+        let pylon = &found["other"];
+        assert_eq!((pylon.ip.as_str(), pylon.port), ("192.168.1.20", 7000));
+    }
+
+    #[tokio::test]
+    async fn hears_beacons_on_loopback() {
+        let lan_port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = Config {
+            id: Some("self".into()),
+            lan_discovery: Some(true),
+            lan_port: Some(lan_port),
+            lan_broadcast: Some("127.0.0.1".into()),
+            ..Default::default()
+        };
+        let found = SharedLan::default();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(run_lan_discovery(Arc::new(RwLock::new(config)), Arc::clone(&found), 7000, shutdown_rx));
+
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for _ in 0..50 {
+            sender.send_to(&beacon_from("other", 7100), ("127.0.0.1", lan_port)).await.unwrap();
+            if found.lock().unwrap().contains_key("other") {
+                break;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+        {
+            let found = found.lock().unwrap();
+            assert_eq!(found.get("other").map(|pylon| (pylon.ip.as_str(), pylon.port)), Some(("127.0.0.1", 7100)));
+            // Its own beacon, looped back to it, is not listed.
+            assert!(!found.contains_key("self"));
+        }
+        shutdown_tx.send(true).unwrap();
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn adoption_introduces_the_bound_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer_port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            let reply = r#"{"token":"peer-token","name":"peer","location":null,"description":null}"#;
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", reply.len(), reply);
+            reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+            serde_json::from_slice::<AdoptRequest>(&body).unwrap()
+        });
+
+        let found = SharedLan::default();
+        found.lock().unwrap().insert("peer".into(), LanPylon {
+            id: "peer".into(),
+            ip: "127.0.0.1".into(),
+            port: peer_port,
+            name: None,
+            location: None,
+            version: PYLON_VERSION.into(),
+            last_seen: Utc::now(),
+        });
+        // Already configured by id, so the exchange happens but nothing is saved.
+        let existing: RemotePylonConfig = serde_json::from_value(json!({"ip": "10.0.0.9", "port": 6989, "token": "t", "id": "peer"})).unwrap();
+        let config = Config {
+            local_port: Some(6989),
+            token: "own-token".into(),
+            lan_secret: Some("shared".into()),
+            remote_pylons: Some(vec![existing]),
+            ..Default::default()
+        };
+        let pairing = SharedPairing::default();
+        let result = adopt(&Arc::new(RwLock::new(config)), &pairing, &found, "peer", None, 6991).await;
+        assert!(result.unwrap_err().contains("already configured"));
+        let request = server.await.unwrap();
+        assert_eq!((request.port, request.secret.as_str()), (6991, "shared"));
+        // A credential of its own, not our token, and withdrawn since nothing was adopted.
+        assert_ne!(request.token, "own-token");
+        assert!(!pairing.lock().unwrap().is_active(&request.token));
+    }
+
+    #[tokio::test]
+    async fn adoption_needs_a_secret_or_token() {
+        let found = SharedLan::default();
+        found.lock().unwrap().insert("peer".into(), LanPylon {
+            id: "peer".into(),
+            ip: "127.0.0.1".into(),
+            port: 1,
+            name: None,
+            location: None,
+            version: PYLON_VERSION.into(),
+            last_seen: Utc::now(),
+        });
+        let config = Arc::new(RwLock::new(Config::default()));
+        let pairing = SharedPairing::default();
+        assert!(adopt(&config, &pairing, &found, "missing", None, 6989).await.unwrap_err().contains("no pylon"));
+        assert!(adopt(&config, &pairing, &found, "peer", None, 6989).await.unwrap_err().contains("lan_secret"));
+        assert!(pairing.lock().unwrap().credentials.is_empty());
+    }
+
+    #[test]
+    fn answers_adoption_only_with_the_shared_secret() {
+        let discovery = Arc::new(Mutex::new(crate::discovery::Discovery::default()));
+        let request = |secret: &str| AdoptRequest {
+            secret: secret.into(),
+            name: None,
+            location: None,
+            description: None,
+            port: 6989,
+            token: "adopter".into(),
+            id: None,
+        };
+        let pairing = SharedPairing::default();
+        let disabled = Arc::new(RwLock::new(Config { lan_secret: Some("shared".into()), ..Default::default() }));
+        assert!(answer_adoption(&disabled, &pairing, &discovery, request("shared"), "127.0.0.1".into()).is_err());
+        let enabled = Arc::new(RwLock::new(Config { lan_discovery: Some(true), lan_secret: Some("shared".into()), ..Default::default() }));
+        assert_eq!(answer_adoption(&enabled, &pairing, &discovery, request("wrong"), "127.0.0.1".into()).err().as_deref(), Some("wrong lan_secret"));
+        assert!(pairing.lock().unwrap().credentials.is_empty());
+    }
+}
@@ -0,0 +1,405 @@
+// src/log_watcher.rs
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::{channel, TryRecvError};
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Utc};
+use notify::{watcher, RecursiveMode, Watcher};
+use regex::Regex;
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+use crate::config_manager::{Config, LogWatchConfig};
+use crate::system_info::SystemData;
+
+/// How many per-minute buckets are kept for each pattern.
+const MINUTES_KEPT: usize = 60;
+/// Longer lines are cut here; the rest of the line is skipped until its newline.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct PatternCounter {
+    pub pattern: String,
+    pub total: u64,
+    // Matches per minute, oldest first; the last entry is the current minute.
+    pub per_minute: VecDeque<u64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LogLine {
+    pub timestamp: DateTime<Utc>,
+    pub pattern: String,
+    pub line: String,
+}
+
+/// The state of one watched log file.
+#[derive(Debug, Serialize, Clone)]
+pub struct LogFileStatus {
+    pub path: String,
+    pub counters: Vec<PatternCounter>,
+    // The most recent matching lines, oldest first.
+    pub recent_lines: VecDeque<LogLine>,
+    pub rotations: u64,
+    pub error: Option<String>,
+}
+
+#[cfg(unix)]
+fn file_id(meta: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.ino()
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &std::fs::Metadata) -> u64 {
+    0
+}
+
+/// Follows a single file across appends, truncation and rotation.
+struct LogTail {
+    settings: LogWatchConfig,
+    patterns: Vec<Regex>,
+    file: Option<File>,
+    // Whether the file has been looked at before; files that appear later are read from the start.
+    polled: bool,
+    inode: u64,
+    offset: u64,
+    partial: String,
+    // Set while skipping the remainder of a line that outgrew MAX_LINE_BYTES.
+    overlong: bool,
+    minute: i64,
+    status: LogFileStatus,
+}
+
+impl LogTail {
+    fn new(settings: LogWatchConfig) -> Self {
+        let mut errors = Vec::new();
+        let patterns: Vec<Regex> = settings.patterns.iter()
+            .filter_map(|p| Regex::new(p).map_err(|e| errors.push(format!("{}: {}", p, e))).ok())
+            .collect();
+        let status = LogFileStatus {
+            path: settings.path.clone(),
+            counters: patterns.iter().map(|re| PatternCounter {
+                pattern: re.as_str().to_string(),
+                total: 0,
+                per_minute: VecDeque::from(vec![0]),
+            }).collect(),
+            recent_lines: VecDeque::new(),
+            rotations: 0,
+            error: if errors.is_empty() { None } else { Some(errors.join("; ")) },
+        };
+        Self {
+            settings,
+            patterns,
+            file: None,
+            polled: false,
+            inode: 0,
+            offset: 0,
+            partial: String::new(),
+            overlong: false,
+            minute: Utc::now().timestamp() / 60,
+            status,
+        }
+    }
+
+    /// Starts a new per-minute bucket for every minute that has passed.
+    fn roll_minutes(&mut self) -> bool {
+        let minute = Utc::now().timestamp() / 60;
+        if minute == self.minute {
+            return false;
+        }
+        let elapsed = (minute - self.minute).clamp(0, MINUTES_KEPT as i64);
+        self.minute = minute;
+        for counter in self.status.counters.iter_mut() {
+            for _ in 0..elapsed {
+                counter.per_minute.push_back(0);
+            }
+            while counter.per_minute.len() > MINUTES_KEPT {
+                counter.per_minute.pop_front();
+            }
+        }
+        true
+    }
+
+    /// Reads everything from `file` past `offset`.
+    fn drain(file: &mut File, offset: &mut u64) -> std::io::Result<String> {
+        file.seek(SeekFrom::Start(*offset))?;
+        let mut buf = Vec::new();
+        let read = file.read_to_end(&mut buf)?;
+        *offset += read as u64;
+        Ok(String::from_utf8_lossy(&buf).to_string())
+    }
+
+    /// Reads new data, reopening the file when it was rotated or truncated.
+    /// Returns true when any counter changed.
+    fn poll(&mut self) -> bool {
+        let at_startup = !std::mem::replace(&mut self.polled, true);
+        let meta = match std::fs::metadata(&self.settings.path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                let error = Some(e.to_string());
+                let changed = self.status.error != error;
+                self.status.error = error;
+                return changed;
+            }
+        };
+
+        let mut text = String::new();
+        let inode = file_id(&meta);
+        let first_open = self.file.is_none();
+        if first_open || inode != self.inode {
+            // Finish the old file (still readable through our handle after a rename).
+            if let Some(mut old) = self.file.take() {
+                if let Ok(rest) = Self::drain(&mut old, &mut self.offset) {
+                    text.push_str(&rest);
+                }
+                self.status.rotations += 1;
+            }
+            match File::open(&self.settings.path) {
+                Ok(file) => {
+                    self.file = Some(file);
+                    self.inode = inode;
+                    // Only count lines written after we started watching; a file
+                    // created since then is new to us in full.
+                    self.offset = if first_open && at_startup { meta.len() } else { 0 };
+                    self.status.error = None;
+                },
+                Err(e) => {
+                    self.status.error = Some(e.to_string());
+                    return true;
+                }
+            }
+        } else if meta.len() < self.offset {
+            // Truncated in place (copytruncate).
+            self.offset = 0;
+            self.status.rotations += 1;
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            match Self::drain(file, &mut self.offset) {
+                Ok(new) => text.push_str(&new),
+                Err(e) => self.status.error = Some(e.to_string()),
+            }
+        }
+        if text.is_empty() {
+            return false;
+        }
+
+        let mut text = text.as_str();
+        if self.overlong {
+            // The newline ends the truncated line already held in `partial`.
+            match text.find('\n') {
+                Some(idx) => {
+                    text = &text[idx..];
+                    self.overlong = false;
+                },
+                None => return false,
+            }
+        }
+        self.partial.push_str(text);
+        let complete = match self.partial.rfind('\n') {
+            Some(idx) => {
+                let rest = self.partial.split_off(idx + 1);
+                std::mem::replace(&mut self.partial, rest)
+            },
+            None => String::new(),
+        };
+        if self.partial.len() > MAX_LINE_BYTES {
+            let mut cut = MAX_LINE_BYTES;
+            while !self.partial.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            self.partial.truncate(cut);
+            self.overlong = true;
+        }
+
+        let keep_lines = self.settings.keep_lines.unwrap_or(50);
+        let mut changed = false;
+        for line in complete.lines() {
+            for (re, counter) in self.patterns.iter().zip(self.status.counters.iter_mut()) {
+                if re.is_match(line) {
+                    counter.total += 1;
+                    if let Some(current) = counter.per_minute.back_mut() {
+                        *current += 1;
+                    }
+                    self.status.recent_lines.push_back(LogLine {
+                        timestamp: Utc::now(),
+                        pattern: counter.pattern.clone(),
+                        line: line.to_string(),
+                    });
+                    changed = true;
+                }
+            }
+        }
+        while self.status.recent_lines.len() > keep_lines {
+            self.status.recent_lines.pop_front();
+        }
+        changed
+    }
+}
+
+fn build_tails(settings: &[LogWatchConfig]) -> Vec<LogTail> {
+    settings.iter().cloned().map(LogTail::new).collect()
+}
+
+/// Polls every tail on the blocking thread pool, since the reads use std::fs.
+/// Returns the tails and whether any status changed, or None if the task panicked.
+async fn poll_tails(mut tails: Vec<LogTail>, roll: bool) -> Option<(Vec<LogTail>, bool)> {
+    tokio::task::spawn_blocking(move || {
+        let mut changed = false;
+        for tail in tails.iter_mut() {
+            if roll {
+                changed |= tail.roll_minutes();
+            }
+            changed |= tail.poll();
+        }
+        (tails, changed)
+    }).await.ok()
+}
+
+/// Tails every configured log file, counting pattern matches per minute.
+/// File system events wake the loop early; a periodic poll catches anything missed.
+pub async fn watch_logs(
+    data: Arc<Mutex<SystemData>>,
+    config_arc: Arc<RwLock<Config>>,
+    shutdown: watch::Receiver<bool>
+) {
+    let (tx, rx) = channel();
+    let mut fs_watcher = match watcher(tx, Duration::from_millis(500)) {
+        Ok(w) => w,
+        Err(e) => {
+            println!("Failed to start log watcher: {}", e);
+            return;
+        }
+    };
+
+    let mut settings: Vec<LogWatchConfig> = Vec::new();
+    let mut tails: Vec<LogTail> = Vec::new();
+    let mut last_poll = std::time::Instant::now();
+    loop {
+        if *shutdown.borrow() {
+            println!("Shutting down log watcher.");
+            break;
+        }
+
+        // Pick up config changes.
+        let current = config_arc.read().unwrap().log_watches.clone().unwrap_or_default();
+        if current != settings {
+            for old in settings.iter() {
+                if let Some(dir) = Path::new(&old.path).parent() {
+                    let _ = fs_watcher.unwatch(dir);
+                }
+            }
+            for new in current.iter() {
+                if let Some(dir) = Path::new(&new.path).parent() {
+                    if let Err(e) = fs_watcher.watch(dir, RecursiveMode::NonRecursive) {
+                        println!("Failed to watch {:?}: {}", dir, e);
+                    }
+                }
+            }
+            tails = poll_tails(build_tails(&current), false).await
+                .map(|(tails, _)| tails)
+                .unwrap_or_default();
+            settings = current;
+            data.lock().unwrap().logs = tails.iter().map(|t| t.status.clone()).collect();
+        }
+
+        let woken = match rx.try_recv() {
+            Ok(_) => true,
+            Err(TryRecvError::Empty) => false,
+            Err(TryRecvError::Disconnected) => {
+                println!("Log watcher disconnected.");
+                break;
+            }
+        };
+        // A busy directory sends a burst of events; one poll covers them all.
+        while rx.try_recv().is_ok() {}
+
+        if woken || last_poll.elapsed() >= Duration::from_secs(2) {
+            last_poll = std::time::Instant::now();
+            match poll_tails(std::mem::take(&mut tails), true).await {
+                Some((polled, changed)) => {
+                    tails = polled;
+                    if changed {
+                        data.lock().unwrap().logs = tails.iter().map(|t| t.status.clone()).collect();
+                    }
+                },
+                // Rebuild the tails on the next pass.
+                None => settings.clear(),
+            }
+        }
+
+        sleep(Duration::from_millis(if woken { 100 } else { 250 })).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tail_in(name: &str) -> (LogTail, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("pylon-log-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let _ = std::fs::remove_file(&path);
+        let tail = LogTail::new(LogWatchConfig {
+            path: path.to_string_lossy().into(),
+            patterns: vec!["ERROR".into()],
+            keep_lines: None,
+        });
+        (tail, path)
+    }
+
+    #[test]
+    fn skips_existing_lines_at_startup() {
+        let (mut tail, path) = tail_in("existing");
+        std::fs::write(&path, "ERROR old\n").unwrap();
+        assert!(!tail.poll());
+        std::fs::write(&path, "ERROR old\nERROR new\n").unwrap();
+        assert!(tail.poll());
+        assert_eq!(tail.status.counters[0].total, 1);
+    }
+
+    #[test]
+    fn reads_file_created_after_startup_from_the_start() {
+        let (mut tail, path) = tail_in("late");
+        assert!(tail.poll());
+        assert!(tail.status.error.is_some());
+        std::fs::write(&path, "ERROR first\ninfo\nERROR second\n").unwrap();
+        assert!(tail.poll());
+        assert_eq!(tail.status.counters[0].total, 2);
+        assert!(tail.status.error.is_none());
+    }
+
+    #[test]
+    fn waits_for_complete_lines_and_follows_truncation() {
+        let (mut tail, path) = tail_in("partial");
+        tail.poll();
+        std::fs::write(&path, "ERROR half").unwrap();
+        assert!(!tail.poll());
+        std::fs::write(&path, "ERROR half line\n").unwrap();
+        assert!(tail.poll());
+        std::fs::write(&path, "ERROR\n").unwrap();
+        assert!(tail.poll());
+        assert_eq!(tail.status.counters[0].total, 2);
+        assert_eq!(tail.status.rotations, 1);
+    }
+
+    #[test]
+    fn caps_overlong_lines() {
+        let (mut tail, path) = tail_in("overlong");
+        tail.poll();
+        let long = "x".repeat(MAX_LINE_BYTES * 2);
+        std::fs::write(&path, format!("ERROR {}", long)).unwrap();
+        assert!(!tail.poll());
+        assert_eq!(tail.partial.len(), MAX_LINE_BYTES);
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut file, format!("{}\nERROR next\n", long).as_bytes()).unwrap();
+        assert!(tail.poll());
+        assert_eq!(tail.status.counters[0].total, 2);
+        assert_eq!(tail.status.recent_lines[0].line.len(), MAX_LINE_BYTES);
+        assert!(tail.partial.is_empty());
+    }
+}
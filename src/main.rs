@@ -2,7 +2,21 @@
 
 mod config_manager;
 mod system_info;
+mod certs;
+mod log_watcher;
+mod sockets;
+mod history;
+mod anomaly;
+mod forecast;
+mod flapping;
+mod federation;
+mod alerts;
+mod notifiers;
+mod discovery;
+mod lan;
+mod pairing;
 mod remote;
+mod upstream;
 mod server;
 mod updater; // <-- New updater module
 
@@ -33,10 +47,19 @@ async fn main() -> std::io::Result<()> {
     }
 
     let initial_config = match config_manager::load_config() {
-        Ok(cfg) => cfg,
+        Ok(mut cfg) => {
+            if cfg.id.is_none() {
+                cfg.id = Some(config_manager::generate_id());
+                match config_manager::save_config(&cfg) {
+                    Ok(()) => println!("Generated pylon id {}", cfg.id.as_deref().unwrap_or_default()),
+                    Err(e) => println!("Failed to save the generated pylon id: {}", e),
+                }
+            }
+            cfg
+        },
         Err(e) => {
             println!("Failed to load config: {}. Using default.", e);
-            Config::default()
+            Config { id: Some(config_manager::generate_id()), ..Config::default() }
         }
     };
     let config = Arc::new(RwLock::new(initial_config));
@@ -46,29 +69,59 @@ async fn main() -> std::io::Result<()> {
     let config_clone = Arc::clone(&config);
     tokio::spawn(config_manager::watch_config(config_clone, shutdown_rx.clone()));
 
+    let history_settings = config.read().unwrap().history.clone().unwrap_or_default();
+    let history = Arc::new(Mutex::new(history::HistoryStore::open(&history_settings)));
+    tokio::spawn(history::maintain_history(Arc::clone(&history), shutdown_rx.clone()));
+
     let system_data = Arc::new(Mutex::new(SystemData::new()));
     let system_data_clone = Arc::clone(&system_data);
-    // --- Pass the config into the system metrics poller ---
-    tokio::spawn(system_info::poll_system_metrics(system_data_clone, Arc::clone(&config), shutdown_rx.clone()));
+    // --- Pass the config and history store into the system metrics poller ---
+    tokio::spawn(system_info::poll_system_metrics(system_data_clone, Arc::clone(&config), Arc::clone(&history), shutdown_rx.clone()));
+    tokio::spawn(system_info::poll_software_versions(Arc::clone(&system_data), Arc::clone(&config), shutdown_rx.clone()));
+    tokio::spawn(system_info::poll_package_status(Arc::clone(&system_data), Arc::clone(&config), shutdown_rx.clone()));
+    tokio::spawn(certs::poll_certificates(Arc::clone(&system_data), Arc::clone(&config), shutdown_rx.clone()));
+    tokio::spawn(log_watcher::watch_logs(Arc::clone(&system_data), Arc::clone(&config), shutdown_rx.clone()));
+    tokio::spawn(sockets::poll_sockets(Arc::clone(&system_data), Arc::clone(&config), shutdown_rx.clone()));
+    tokio::spawn(forecast::poll_forecasts(Arc::clone(&system_data), Arc::clone(&history), shutdown_rx.clone()));
+
+    // Found before starting the tasks that tell other pylons our port.
+    let base_port = config.read().unwrap().local_port.unwrap_or(6989);
+    let server_port = server::find_open_port(base_port).await;
+    if server_port != base_port {
+        println!("Port {} was in use. Running on port {} instead.", base_port, server_port);
+    }
 
     let remote_statuses = Arc::new(Mutex::new(HashMap::<String, RemoteStatus>::new()));
     let config_clone2 = Arc::clone(&config);
     let remote_statuses_clone = Arc::clone(&remote_statuses);
-    tokio::spawn(remote::poll_remote_pylons(config_clone2, remote_statuses_clone, shutdown_rx.clone()));
+    let (ingest_tx, ingest_rx) = tokio::sync::mpsc::unbounded_channel();
+    let discovery = Arc::new(Mutex::new(discovery::Discovery::load()));
+    tokio::spawn(discovery::expire_peers(Arc::clone(&config), Arc::clone(&discovery), shutdown_rx.clone()));
+    let lan = Arc::new(Mutex::new(HashMap::new()));
+    tokio::spawn(lan::run_lan_discovery(Arc::clone(&config), Arc::clone(&lan), server_port, shutdown_rx.clone()));
+    tokio::spawn(remote::poll_remote_pylons(config_clone2, remote_statuses_clone, Arc::clone(&history), Arc::clone(&discovery), ingest_rx, shutdown_rx.clone()));
+    tokio::spawn(upstream::push_to_upstream(Arc::clone(&config), Arc::clone(&system_data), server_port, shutdown_rx.clone()));
+
+    let (notify_tx, notify_rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(notifiers::dispatch_notifications(Arc::clone(&config), notify_rx, shutdown_rx.clone()));
+    let alerts = Arc::new(Mutex::new(alerts::AlertManager::new(notify_tx)));
+    tokio::spawn(alerts::evaluate_alerts(Arc::clone(&config), Arc::clone(&system_data), Arc::clone(&remote_statuses), Arc::clone(&history), Arc::clone(&alerts), shutdown_rx.clone()));
 
     let config_clone3 = Arc::clone(&config);
     tokio::spawn(updater::auto_update_loop(config_clone3, shutdown_rx.clone()));
 
-    let base_port = config.read().unwrap().local_port.unwrap_or(6989);
-    let server_port = server::find_open_port(base_port).await;
-    if server_port != base_port {
-        println!("Port {} was in use. Running on port {} instead.", base_port, server_port);
-    }
-
     let state = AppState {
         config: Arc::clone(&config),
         system_data: Arc::clone(&system_data),
         remote_statuses: Arc::clone(&remote_statuses),
+        history: Arc::clone(&history),
+        alerts: Arc::clone(&alerts),
+        ingest: ingest_tx,
+        discovery: Arc::clone(&discovery),
+        lan: Arc::clone(&lan),
+        pairing: Arc::new(Mutex::new(pairing::Pairing::load())),
+        federation: Arc::new(tokio::sync::Mutex::new(None)),
+        port: server_port,
     };
 
     let server = server::run_server(server_port, state);
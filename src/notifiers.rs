@@ -0,0 +1,474 @@
+// src/notifiers.rs
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use chrono::{DateTime, Utc};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::Client;
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep, Duration};
+use crate::alerts::{Alert, AlertState};
+use crate::config_manager::{Config, NotifierConfig};
+
+/// One-line summary used as title, subject and chat message.
+fn summary(alert: &Alert) -> String {
+    let state = match alert.state {
+        AlertState::Pending => "PENDING",
+        AlertState::Firing => "FIRING",
+        AlertState::Resolved => "RESOLVED",
+    };
+    format!("[{}] {} on {}: {}", state, alert.id, alert.pylon, alert.message)
+}
+
+/// Replaces {{field}} placeholders with alert fields in a single pass, so
+/// placeholders inside substituted values are left alone. Values are
+/// JSON-escaped (without quotes) so they can be dropped into a JSON template.
+fn render(template: &str, alert: &Alert) -> String {
+    let mut fields = match serde_json::to_value(alert) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Default::default(),
+    };
+    fields.insert("summary".into(), json!(summary(alert)));
+
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        body.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let field = after.find("}}").and_then(|end| fields.get(&after[..end]).map(|value| (end, value)));
+        match field {
+            Some((end, value)) => {
+                let text = match value {
+                    Value::String(text) => text.clone(),
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                let escaped = serde_json::to_string(&text).unwrap_or_default();
+                body.push_str(&escaped[1..escaped.len() - 1]);
+                rest = &after[end + 2..];
+            },
+            // Unknown placeholders are kept as written.
+            None => {
+                body.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    body.push_str(rest);
+    body
+}
+
+fn require<'a>(value: &'a Option<String>, field: &str) -> Result<&'a str, String> {
+    value.as_deref().ok_or_else(|| format!("missing '{}'", field))
+}
+
+async fn post(request: reqwest::RequestBuilder) -> Result<(), String> {
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    }
+}
+
+async fn send_email(notifier: &NotifierConfig, alert: &Alert) -> Result<(), String> {
+    let host = require(&notifier.smtp_host, "smtp_host")?;
+    let from: Mailbox = require(&notifier.from, "from")?.parse().map_err(|e| format!("invalid 'from': {}", e))?;
+    let mut builder = Message::builder().from(from).subject(summary(alert));
+    for to in notifier.to.iter().flatten() {
+        builder = builder.to(to.parse().map_err(|e| format!("invalid 'to' {}: {}", to, e))?);
+    }
+    let text = format!("{}\n\nSeverity: {}\n{}", summary(alert), alert.severity, alert.description.clone().unwrap_or_default());
+    let email = builder.body(text).map_err(|e| e.to_string())?;
+
+    let mut transport = if notifier.smtp_starttls.unwrap_or(true) {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host).map_err(|e| e.to_string())?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+    };
+    if let Some(port) = notifier.smtp_port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&notifier.smtp_username, &notifier.smtp_password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport.timeout(Some(Duration::from_secs(10))).build().send(email).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Sends one alert through one channel (a single attempt).
+async fn send(client: &Client, notifier: &NotifierConfig, alert: &Alert) -> Result<(), String> {
+    let text = summary(alert);
+    match notifier.kind.as_str() {
+        "webhook" => {
+            let url = require(&notifier.url, "url")?;
+            let body = match &notifier.body {
+                Some(template) => render(template, alert),
+                None => json!({"pylon": alert.pylon, "alert": alert}).to_string(),
+            };
+            let mut request = client.post(url).header("Content-Type", "application/json").body(body);
+            for (name, value) in notifier.headers.iter().flatten() {
+                request = request.header(name, value);
+            }
+            post(request).await
+        },
+        "slack" | "mattermost" => post(client.post(require(&notifier.url, "url")?).json(&json!({"text": text}))).await,
+        "discord" => post(client.post(require(&notifier.url, "url")?).json(&json!({"content": text}))).await,
+        "ntfy" => {
+            let priority = if alert.severity == "critical" && alert.state == AlertState::Firing { "urgent" } else { "default" };
+            let mut request = client.post(require(&notifier.url, "url")?)
+                .header("Title", format!("{} ({})", alert.rule, alert.pylon))
+                .header("Priority", priority)
+                .header("Tags", if alert.state == AlertState::Resolved { "white_check_mark" } else { "rotating_light" })
+                .body(text);
+            if let Some(token) = &notifier.token {
+                request = request.bearer_auth(token);
+            }
+            post(request).await
+        },
+        "gotify" => {
+            let url = format!("{}/message", require(&notifier.url, "url")?.trim_end_matches('/'));
+            let priority = if alert.severity == "critical" && alert.state == AlertState::Firing { 8 } else { 5 };
+            post(client.post(url)
+                .header("X-Gotify-Key", require(&notifier.token, "token")?)
+                .json(&json!({"title": format!("{} ({})", alert.rule, alert.pylon), "message": text, "priority": priority}))).await
+        },
+        "email" => send_email(notifier, alert).await,
+        other => Err(format!("unknown notifier kind '{}'", other)),
+    }
+}
+
+/// Sends with exponential backoff (2s, 4s, ... up to 64s) between attempts.
+async fn send_with_retries(client: Client, notifier: NotifierConfig, alert: Alert) {
+    let attempts = notifier.retries.unwrap_or(3).max(1);
+    for attempt in 1..=attempts {
+        match send(&client, &notifier, &alert).await {
+            Ok(()) => {
+                println!("Sent alert {} to notifier {}", alert.id, notifier.name);
+                return;
+            },
+            Err(e) => {
+                println!("Notifier {} failed (attempt {}/{}): {}", notifier.name, attempt, attempts, e);
+                if attempt < attempts {
+                    sleep(Duration::from_secs(1u64 << attempt.min(6))).await;
+                }
+            }
+        }
+    }
+}
+
+/// Sliding one-hour window of send times per channel.
+#[derive(Default)]
+struct RateLimiter {
+    sent: HashMap<String, VecDeque<DateTime<Utc>>>,
+}
+
+impl RateLimiter {
+    fn allow(&mut self, notifier: &NotifierConfig, now: DateTime<Utc>) -> bool {
+        let sent = self.sent.entry(notifier.name.clone()).or_default();
+        while sent.front().is_some_and(|ts| (now - *ts).num_seconds() >= 3600) {
+            sent.pop_front();
+        }
+        if sent.len() >= notifier.max_per_hour.unwrap_or(30) as usize {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+}
+
+fn http_client() -> Client {
+    Client::builder().timeout(Duration::from_secs(10)).build().unwrap_or_default()
+}
+
+/// Delivers firing and resolved alerts from the alert engine to every
+/// configured `[[notifiers]]` channel.
+pub async fn dispatch_notifications(
+    config_arc: Arc<RwLock<Config>>,
+    mut alerts: mpsc::UnboundedReceiver<Alert>,
+    mut shutdown: watch::Receiver<bool>
+) {
+    let client = http_client();
+    let mut limiter = RateLimiter::default();
+    loop {
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down notifier.");
+                    break;
+                }
+            },
+            alert = alerts.recv() => {
+                let alert = match alert {
+                    Some(alert) => alert,
+                    None => break,
+                };
+                let notifiers = config_arc.read().unwrap().notifiers.clone().unwrap_or_default();
+                for notifier in notifiers {
+                    if !limiter.allow(&notifier, Utc::now()) {
+                        println!("Notifier {} is rate limited; dropping alert {}", notifier.name, alert.id);
+                        continue;
+                    }
+                    tokio::spawn(send_with_retries(client.clone(), notifier, alert.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// Sends a test alert to the named channel (or all channels) once, without
+/// retries or rate limiting, and reports the outcome per channel. Fails when
+/// no channel has the given name.
+pub async fn test_send(config_arc: &Arc<RwLock<Config>>, name: Option<&str>) -> Result<Vec<Value>, String> {
+    let (notifiers, pylon) = {
+        let config = config_arc.read().unwrap();
+        (config.notifiers.clone().unwrap_or_default(), config.name.clone().unwrap_or_else(|| "Pylon".into()))
+    };
+    let now = Utc::now();
+    let alert = Alert {
+        id: "test".into(),
+        rule: "test".into(),
+        instance: String::new(),
+        message: format!("Test notification from {}", pylon),
+        pylon,
+        severity: "info".into(),
+        labels: HashMap::new(),
+        state: AlertState::Firing,
+        value: None,
+        description: None,
+        pending_since: now,
+        fired_at: Some(now),
+        resolved_at: None,
+        acknowledged_at: None,
+        ack_comment: None,
+        silenced_by: None,
+        notified: false,
+    };
+
+    let selected: Vec<&NotifierConfig> = notifiers.iter().filter(|n| name.is_none_or(|name| n.name == name)).collect();
+    if let (Some(name), true) = (name, selected.is_empty()) {
+        return Err(format!("No notifier named '{}'", name));
+    }
+    let client = http_client();
+    let mut results = Vec::new();
+    for notifier in selected {
+        let result = send(&client, notifier, &alert).await;
+        results.push(json!({"name": notifier.name, "ok": result.is_ok(), "error": result.err()}));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn notifier(value: Value) -> NotifierConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn remote_alert() -> Alert {
+        let now = Utc::now();
+        Alert {
+            id: "remote_offline:web-2".into(),
+            rule: "remote_offline".into(),
+            instance: "web-2".into(),
+            pylon: "web-2".into(),
+            severity: "critical".into(),
+            labels: HashMap::new(),
+            state: AlertState::Firing,
+            value: None,
+            message: "web-2 is offline".into(),
+            description: None,
+            pending_since: now,
+            fired_at: Some(now),
+            resolved_at: None,
+            acknowledged_at: None,
+            ack_comment: None,
+            silenced_by: None,
+            notified: false,
+        }
+    }
+
+    /// Answers one HTTP request with `status` and hands back the request head and body.
+    async fn http_stub(status: u16) -> (String, tokio::task::JoinHandle<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if line == "\r\n" || line.is_empty() {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let length = head.lines()
+                .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            let response = format!("HTTP/1.1 {} Stub\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+            (head, String::from_utf8(body).unwrap())
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn webhook_reports_the_pylon_the_alert_is_about() {
+        let (url, request) = http_stub(200).await;
+        let webhook = notifier(json!({
+            "name": "hook", "kind": "webhook", "url": format!("{}/hook", url),
+            "headers": {"X-Team": "ops"},
+        }));
+        send(&http_client(), &webhook, &remote_alert()).await.unwrap();
+        let (head, body) = request.await.unwrap();
+        assert!(head.starts_with("POST /hook "));
+        assert!(head.to_ascii_lowercase().contains("x-team: ops"));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["pylon"], "web-2");
+        assert_eq!(body["alert"]["id"], "remote_offline:web-2");
+    }
+
+    #[tokio::test]
+    async fn webhook_template_is_json_escaped() {
+        let (url, request) = http_stub(200).await;
+        let mut alert = remote_alert();
+        alert.message = "say \"hi\"".into();
+        let webhook = notifier(json!({
+            "name": "hook", "kind": "webhook", "url": url,
+            "body": "{\"text\": \"{{summary}}\", \"where\": \"{{pylon}}\", \"missing\": \"{{value}}\"}",
+        }));
+        send(&http_client(), &webhook, &alert).await.unwrap();
+        let body: Value = serde_json::from_str(&request.await.unwrap().1).unwrap();
+        assert_eq!(body["text"], "[FIRING] remote_offline:web-2 on web-2: say \"hi\"");
+        assert_eq!(body["where"], "web-2");
+        assert_eq!(body["missing"], "");
+    }
+
+    #[test]
+    fn template_values_are_not_substituted_again() {
+        let mut alert = remote_alert();
+        alert.message = "{{pylon}} {{severity}}".into();
+        assert_eq!(render("{{message}}/{{pylon}}/{{unknown}}/{{", &alert), "{{pylon}} {{severity}}/web-2/{{unknown}}/{{");
+    }
+
+    #[tokio::test]
+    async fn chat_and_push_services_get_their_own_payloads() {
+        let (url, request) = http_stub(200).await;
+        send(&http_client(), &notifier(json!({"name": "chat", "kind": "discord", "url": url})), &remote_alert()).await.unwrap();
+        let body: Value = serde_json::from_str(&request.await.unwrap().1).unwrap();
+        assert_eq!(body["content"], "[FIRING] remote_offline:web-2 on web-2: web-2 is offline");
+
+        let (url, request) = http_stub(200).await;
+        let gotify = notifier(json!({"name": "push", "kind": "gotify", "url": format!("{}/", url), "token": "app"}));
+        send(&http_client(), &gotify, &remote_alert()).await.unwrap();
+        let (head, body) = request.await.unwrap();
+        assert!(head.starts_with("POST /message "));
+        assert!(head.to_ascii_lowercase().contains("x-gotify-key: app"));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!((body["title"].as_str(), body["priority"].as_i64()), (Some("remote_offline (web-2)"), Some(8)));
+
+        let (url, request) = http_stub(200).await;
+        send(&http_client(), &notifier(json!({"name": "topic", "kind": "ntfy", "url": url})), &remote_alert()).await.unwrap();
+        let head = request.await.unwrap().0.to_ascii_lowercase();
+        assert!(head.contains("title: remote_offline (web-2)"));
+        assert!(head.contains("priority: urgent"));
+    }
+
+    #[tokio::test]
+    async fn reports_failed_and_misconfigured_sends() {
+        let (url, request) = http_stub(500).await;
+        let error = send(&http_client(), &notifier(json!({"name": "chat", "kind": "slack", "url": url})), &remote_alert()).await;
+        assert!(error.unwrap_err().starts_with("HTTP 500"));
+        request.await.unwrap();
+
+        let error = send(&http_client(), &notifier(json!({"name": "push", "kind": "gotify", "url": "http://127.0.0.1:1"})), &remote_alert()).await;
+        assert_eq!(error.unwrap_err(), "missing 'token'");
+        let error = send(&http_client(), &notifier(json!({"name": "pager", "kind": "pager"})), &remote_alert()).await;
+        assert_eq!(error.unwrap_err(), "unknown notifier kind 'pager'");
+    }
+
+    #[tokio::test]
+    async fn sends_email_over_plain_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut data = String::new();
+            let mut in_data = false;
+            reader.get_mut().write_all(b"220 stub ESMTP\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let reply: &[u8] = if in_data {
+                    if line != ".\r\n" {
+                        data.push_str(&line);
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line.starts_with("QUIT") {
+                    reader.get_mut().write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                reader.get_mut().write_all(reply).await.unwrap();
+            }
+            data
+        });
+        let email = notifier(json!({
+            "name": "mail", "kind": "email", "smtp_host": "127.0.0.1", "smtp_port": port,
+            "smtp_starttls": false, "from": "pylon@example.com", "to": ["ops@example.com"],
+        }));
+        send(&http_client(), &email, &remote_alert()).await.unwrap();
+        let data = server.await.unwrap();
+        assert!(data.contains("Subject: [FIRING] remote_offline:web-2 on web-2: web-2 is offline"));
+        assert!(data.contains("To: ops@example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_send_rejects_unknown_names() {
+        let (url, request) = http_stub(200).await;
+        let config = Config {
+            name: Some("central".into()),
+            notifiers: Some(vec![notifier(json!({"name": "hook", "kind": "webhook", "url": url}))]),
+            ..Default::default()
+        };
+        let config = Arc::new(RwLock::new(config));
+        assert_eq!(test_send(&config, Some("nope")).await.unwrap_err(), "No notifier named 'nope'");
+
+        let results = test_send(&config, Some("hook")).await.unwrap();
+        assert_eq!(results, vec![json!({"name": "hook", "ok": true, "error": null})]);
+        let body: Value = serde_json::from_str(&request.await.unwrap().1).unwrap();
+        assert_eq!(body["pylon"], "central");
+    }
+
+    #[test]
+    fn rate_limits_per_channel_per_hour() {
+        let hook = notifier(json!({"name": "hook", "kind": "webhook", "max_per_hour": 2}));
+        let other = notifier(json!({"name": "other", "kind": "webhook", "max_per_hour": 1}));
+        let mut limiter = RateLimiter::default();
+        let now = Utc::now();
+        assert!(limiter.allow(&hook, now));
+        assert!(limiter.allow(&hook, now + chrono::Duration::seconds(1)));
+        assert!(!limiter.allow(&hook, now + chrono::Duration::seconds(2)));
+        assert!(limiter.allow(&other, now));
+        assert!(limiter.allow(&hook, now + chrono::Duration::seconds(3600)));
+    }
+}
@@ -0,0 +1,420 @@
+// src/pairing.rs
+
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use crate::config_manager::{save_config, Config, RemotePylonConfig};
+use crate::discovery::SharedDiscovery;
+
+const CREDENTIALS_FILE: &str = "credentials.json";
+/// How long a pairing code can be used.
+const CODE_LIFETIME_MINUTES: i64 = 10;
+/// Wrong codes tolerated before every outstanding code is discarded.
+const MAX_FAILED_ATTEMPTS: u32 = 10;
+/// Wrong `lan_secret`s tolerated before adoption is refused for a while.
+const MAX_FAILED_ADOPTIONS: u32 = 10;
+const ADOPTION_LOCKOUT_MINUTES: i64 = 10;
+// No 0/O, 1/I/L, so codes survive being read out loud.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+
+/// A one-time code that lets another pylon pair with us.
+#[derive(Debug, Serialize, Clone)]
+pub struct PairingCode {
+    pub code: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A credential we issued to one paired pylon, which it uses to poll us.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PeerCredential {
+    pub id: String,
+    pub name: Option<String>,
+    // "ip:port" of the paired pylon.
+    pub address: String,
+    // The paired pylon's id; pushes made with this credential must carry it.
+    #[serde(default)]
+    pub pylon_id: Option<String>,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Outstanding pairing codes (in memory only) and issued credentials
+/// (persisted to credentials.json).
+#[derive(Debug, Default)]
+pub struct Pairing {
+    codes: Vec<PairingCode>,
+    failed_attempts: u32,
+    failed_adoptions: u32,
+    adoption_locked_until: Option<DateTime<Utc>>,
+    pub credentials: Vec<PeerCredential>,
+    // Set by `load`; a default store lives in memory only.
+    persist: bool,
+}
+
+pub type SharedPairing = Arc<Mutex<Pairing>>;
+
+/// Sent by the joining pylon to the pylon that generated the code.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PairRequest {
+    pub code: String,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub port: u16,
+    // Credential the joiner issued for us, to poll it back.
+    pub credential: String,
+    // The joiner's pylon id.
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+/// The answer: the credential the joiner polls us with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PairResponse {
+    pub credential: String,
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+}
+
+fn random_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Uppercase without separators, so "k7q4-m2xp" matches "K7Q4M2XP".
+fn normalize(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+impl Pairing {
+    pub fn load() -> Self {
+        let credentials = match std::fs::read_to_string(CREDENTIALS_FILE) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                println!("Failed to parse {}: {}", CREDENTIALS_FILE, e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self { credentials, persist: true, ..Default::default() }
+    }
+
+    fn save(&self) {
+        if !self.persist {
+            return;
+        }
+        let result = serde_json::to_string_pretty(&self.credentials)
+            .map_err(std::io::Error::other)
+            .and_then(|text| std::fs::write(CREDENTIALS_FILE, text));
+        if let Err(e) = result {
+            println!("Failed to save {}: {}", CREDENTIALS_FILE, e);
+        }
+    }
+
+    /// Codes that have not expired or been used.
+    pub fn active_codes(&mut self) -> Vec<PairingCode> {
+        let now = Utc::now();
+        self.codes.retain(|code| code.expires_at > now);
+        self.codes.clone()
+    }
+
+    /// Creates a code shown as "XXXX-XXXX".
+    pub fn generate_code(&mut self) -> PairingCode {
+        let mut rng = rand::thread_rng();
+        let chars: String = (0..8).map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char).collect();
+        let code = PairingCode {
+            code: format!("{}-{}", &chars[..4], &chars[4..]),
+            expires_at: Utc::now() + Duration::minutes(CODE_LIFETIME_MINUTES),
+        };
+        self.codes.push(code.clone());
+        code
+    }
+
+    /// Uses up a code. Too many wrong guesses discard all outstanding codes.
+    fn redeem(&mut self, code: &str) -> bool {
+        self.active_codes();
+        let code = normalize(code);
+        match self.codes.iter().position(|c| normalize(&c.code) == code) {
+            Some(index) => {
+                self.codes.remove(index);
+                true
+            },
+            None => {
+                self.failed_attempts += 1;
+                if self.failed_attempts >= MAX_FAILED_ATTEMPTS {
+                    println!("Too many wrong pairing codes; discarding outstanding codes");
+                    self.codes.clear();
+                    self.failed_attempts = 0;
+                }
+                false
+            }
+        }
+    }
+
+    /// Issues a credential to the pylon at `address` ("ip:port").
+    pub fn issue(&mut self, name: Option<String>, address: String, pylon_id: Option<String>) -> PeerCredential {
+        let credential = PeerCredential {
+            id: format!("{:x}", Utc::now().timestamp_nanos_opt().unwrap_or_default()),
+            name,
+            address,
+            pylon_id,
+            token: random_token(),
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        self.credentials.push(credential.clone());
+        self.save();
+        credential
+    }
+
+    /// Deletes a credential the other pylon never received.
+    pub fn discard(&mut self, id: &str) {
+        self.credentials.retain(|c| c.id != id);
+        self.save();
+    }
+
+    /// Checks an adoption request against our `lan_secret`. Too many wrong
+    /// secrets refuse adoption for a while, even with the right one.
+    pub fn check_adoption(&mut self, secret_matches: bool) -> Result<(), String> {
+        let now = Utc::now();
+        if self.adoption_locked_until.is_some_and(|until| until > now) {
+            return Err("too many failed adoption attempts, try again later".into());
+        }
+        if secret_matches {
+            self.failed_adoptions = 0;
+            return Ok(());
+        }
+        self.failed_adoptions += 1;
+        if self.failed_adoptions >= MAX_FAILED_ADOPTIONS {
+            println!("Too many wrong lan_secrets; refusing adoption for {} minutes", ADOPTION_LOCKOUT_MINUTES);
+            self.adoption_locked_until = Some(now + Duration::minutes(ADOPTION_LOCKOUT_MINUTES));
+            self.failed_adoptions = 0;
+        }
+        Err("wrong lan_secret".into())
+    }
+
+    /// Revokes a credential; the paired pylon can no longer use it.
+    pub fn revoke(&mut self, id: &str) -> bool {
+        match self.credentials.iter_mut().find(|c| c.id == id && c.revoked_at.is_none()) {
+            Some(credential) => {
+                credential.revoked_at = Some(Utc::now());
+                println!("Revoked credential of paired pylon {}", credential.address);
+                self.save();
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Whether `token` is a credential we issued and have not revoked.
+    pub fn is_active(&self, token: &str) -> bool {
+        self.credentials.iter().any(|c| c.token == token && c.revoked_at.is_none())
+    }
+
+    /// Whether `token` is a credential we issued and then revoked.
+    pub fn is_revoked(&self, token: &str) -> bool {
+        self.credentials.iter().any(|c| c.token == token && c.revoked_at.is_some())
+    }
+
+    /// Whether a push authenticated with `token` may report pylon `id`. A
+    /// credential only speaks for the pylon it was issued to; one issued
+    /// before we knew that pylon's id is bound to the first id pushed with
+    /// it. Any other token may not claim the id of a paired pylon.
+    pub fn allows_push(&mut self, token: &str, id: Option<&str>) -> bool {
+        let claimed_elsewhere = |credentials: &[PeerCredential], id: &str| credentials.iter()
+            .any(|c| c.token != token && c.revoked_at.is_none() && c.pylon_id.as_deref() == Some(id));
+        let Some(index) = self.credentials.iter().position(|c| c.token == token && c.revoked_at.is_none()) else {
+            return id.is_none_or(|id| !claimed_elsewhere(&self.credentials, id));
+        };
+        match (self.credentials[index].pylon_id.as_deref(), id) {
+            (Some(bound), pushed) => pushed == Some(bound),
+            (None, Some(pushed)) if claimed_elsewhere(&self.credentials, pushed) => false,
+            (None, Some(pushed)) => {
+                println!("Credential of paired pylon {} belongs to pylon {}", self.credentials[index].address, pushed);
+                self.credentials[index].pylon_id = Some(pushed.to_string());
+                self.save();
+                true
+            },
+            (None, None) => true,
+        }
+    }
+}
+
+/// Pairs with the pylon at `address` ("ip:port") using a code generated
+/// there: issues it a credential to poll us with, receives one to poll it
+/// with, and adds it to `remote_pylons`. The other pylon polls us back on
+/// `own_port`, the port our server is bound to.
+pub async fn join(config_arc: &Arc<RwLock<Config>>, pairing: &SharedPairing, address: &str, code: &str, own_port: u16) -> Result<RemotePylonConfig, String> {
+    let (ip, port) = address.rsplit_once(':')
+        .and_then(|(ip, port)| port.parse::<u16>().ok().map(|port| (ip.to_string(), port)))
+        .ok_or_else(|| format!("invalid address '{}', expected ip:port", address))?;
+    let ours = pairing.lock().unwrap().issue(None, address.to_string(), None);
+    let request = {
+        let config = config_arc.read().unwrap();
+        PairRequest {
+            code: code.to_string(),
+            name: config.name.clone(),
+            location: config.location.clone(),
+            description: config.description.clone(),
+            port: own_port,
+            credential: ours.token.clone(),
+            id: config.id.clone(),
+        }
+    };
+
+    let result = async {
+        let resp = Client::new().post(format!("http://{}:{}/api/pairing/complete", ip, port))
+            .json(&request)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let error = resp.json::<serde_json::Value>().await.ok()
+                .and_then(|body| body["error"].as_str().map(String::from))
+                .unwrap_or_default();
+            return Err(format!("HTTP {} {}", status, error));
+        }
+        resp.json::<PairResponse>().await.map_err(|e| e.to_string())
+    }.await;
+    let theirs = match result {
+        Ok(theirs) => theirs,
+        Err(e) => {
+            // The other side never got it; do not leave an unused credential behind.
+            pairing.lock().unwrap().discard(&ours.id);
+            return Err(e);
+        }
+    };
+    {
+        let mut pairing = pairing.lock().unwrap();
+        if let Some(credential) = pairing.credentials.iter_mut().find(|c| c.id == ours.id) {
+            credential.name = theirs.name.clone();
+            credential.pylon_id = theirs.id.clone();
+        }
+        pairing.save();
+    }
+
+    let remote = RemotePylonConfig {
+        ip,
+        port,
+        token: theirs.credential,
+        id: theirs.id,
+        name: theirs.name,
+        location: theirs.location,
+        description: theirs.description,
+        poll_interval: None,
+        timeout: None,
+        retry_backoff: None,
+        discovered: None,
+        last_seen: None,
+    };
+    let mut config = config_arc.write().unwrap();
+    let remotes = config.remote_pylons.get_or_insert_with(Vec::new);
+    // Pairing again replaces the old token, even from a new address.
+    remotes.retain(|r| !(r.ip == remote.ip && r.port == remote.port) && (r.id.is_none() || r.id != remote.id));
+    remotes.push(remote.clone());
+    save_config(&config).map_err(|e| e.to_string())?;
+    println!("Paired with pylon {}", address);
+    Ok(remote)
+}
+
+/// Completes a pairing started with one of our codes: issues the joiner a
+/// credential and offers it as a peer (see discovery.rs), with the credential
+/// it issued us, so it can be polled back once approved.
+pub fn complete(config_arc: &Arc<RwLock<Config>>, pairing: &SharedPairing, discovery: &SharedDiscovery, request: PairRequest, ip: String) -> Result<PairResponse, String> {
+    let address = format!("{}:{}", ip, request.port);
+    let credential = {
+        let mut pairing = pairing.lock().unwrap();
+        if !pairing.redeem(&request.code) {
+            return Err("invalid or expired pairing code".into());
+        }
+        pairing.issue(request.name.clone(), address.clone(), request.id.clone())
+    };
+    let response = {
+        let config = config_arc.read().unwrap();
+        PairResponse {
+            credential: credential.token,
+            id: config.id.clone(),
+            name: config.name.clone(),
+            location: config.location.clone(),
+            description: config.description.clone(),
+        }
+    };
+    let joiner = RemotePylonConfig {
+        ip,
+        port: request.port,
+        token: request.credential,
+        id: request.id,
+        name: request.name,
+        location: request.location,
+        description: request.description,
+        poll_interval: None,
+        timeout: None,
+        retry_backoff: None,
+        discovered: None,
+        last_seen: None,
+    };
+    println!("Paired with pylon {}", address);
+    discovery.lock().unwrap().advertise(config_arc, joiner, "pairing");
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(token: &str, pylon_id: Option<&str>) -> PeerCredential {
+        PeerCredential {
+            id: token.into(),
+            name: None,
+            address: "10.0.0.2:6989".into(),
+            pylon_id: pylon_id.map(String::from),
+            token: token.into(),
+            created_at: Utc::now(),
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn pushes_are_bound_to_the_credentials_pylon() {
+        let mut pairing = Pairing { credentials: vec![credential("tok-a", Some("pylon-a"))], ..Default::default() };
+        assert!(pairing.allows_push("tok-a", Some("pylon-a")));
+        assert!(!pairing.allows_push("tok-a", Some("pylon-b")));
+        assert!(!pairing.allows_push("tok-a", None));
+        // The main token may push for unpaired pylons, but not as a paired one.
+        assert!(pairing.allows_push("main", Some("pylon-b")));
+        assert!(pairing.allows_push("main", None));
+        assert!(!pairing.allows_push("main", Some("pylon-a")));
+
+        pairing.credentials[0].revoked_at = Some(Utc::now());
+        assert!(pairing.allows_push("main", Some("pylon-a")));
+    }
+
+    #[test]
+    fn credentials_issued_before_the_id_was_known_bind_on_first_push() {
+        let mut pairing = Pairing { credentials: vec![credential("tok-a", None), credential("tok-b", Some("pylon-b"))], ..Default::default() };
+        assert!(!pairing.allows_push("tok-a", Some("pylon-b")));
+        assert!(pairing.allows_push("tok-a", Some("pylon-a")));
+        assert!(!pairing.allows_push("tok-a", Some("pylon-c")));
+    }
+
+    #[test]
+    fn wrong_lan_secrets_lock_adoption() {
+        let mut pairing = Pairing::default();
+        assert!(pairing.check_adoption(true).is_ok());
+        for _ in 1..MAX_FAILED_ADOPTIONS {
+            assert_eq!(pairing.check_adoption(false).unwrap_err(), "wrong lan_secret");
+        }
+        assert!(pairing.check_adoption(true).is_ok());
+        for _ in 0..MAX_FAILED_ADOPTIONS {
+            assert!(pairing.check_adoption(false).is_err());
+        }
+        assert!(pairing.check_adoption(true).unwrap_err().contains("too many"));
+    }
+}
@@ -1,38 +1,616 @@
 // src/remote.rs
 
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use tokio::time::{sleep, Duration};
+use std::collections::{HashMap, HashSet};
+use futures::stream::{FuturesUnordered, StreamExt};
+use rand::Rng;
+use tokio::time::{interval, Duration, Instant};
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 use reqwest::Client;
 use serde_json::Value;
-use tokio::sync::watch;
-use crate::config_manager::Config;
+use tokio::sync::{mpsc, watch};
+use crate::config_manager::{self, save_config, Config, RemoteDefaults, RemotePylonConfig};
+use crate::discovery::{self, SharedDiscovery};
+use crate::flapping::StateTracker;
+use crate::history::{Point, SharedHistory, Tier};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RemoteStatus {
     pub ip: String,
     pub port: u16,
+    // The remote's pylon id, once known.
+    #[serde(default)]
+    pub id: Option<String>,
     pub last_seen: Option<DateTime<Utc>>,
     pub data: Option<Value>, // remote metrics as JSON
     pub online: bool,
     pub name: Option<String>,
     pub location: Option<String>,
     pub description: Option<String>,
+    // Debounced state changes since startup and whether they are too frequent.
+    #[serde(default)]
+    pub transitions: u64,
+    #[serde(default)]
+    pub flapping: bool,
+    // Failed polls in a row (the remote stays online until the hysteresis threshold).
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    // Why the most recent failed poll failed (kept after recovery for reference).
+    #[serde(default)]
+    pub last_error: Option<PollError>,
+    // Response time of the last successful poll.
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    // Pylon version reported by the remote.
+    #[serde(default)]
+    pub version: Option<String>,
+    // Remote clock minus local clock, in seconds (positive when the remote is ahead).
+    #[serde(default)]
+    pub clock_skew_secs: Option<f64>,
+    // Whether the remote pushes its metrics to us instead of being polled.
+    #[serde(default)]
+    pub pushed: bool,
+    // When the remote left `remote_pylons`; its status is dropped after the grace period.
+    #[serde(default)]
+    pub removed_at: Option<DateTime<Utc>>,
 }
 
-/// Now accepts a shutdown receiver so it can exit gracefully.
+/// Why polling a remote failed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PollErrorKind {
+    Dns,
+    ConnectionRefused,
+    // Any other connection failure (unreachable host, reset, ...).
+    Connect,
+    Timeout,
+    Tls,
+    HttpStatus,
+    BadJson,
+    // The remote rejected our token (HTTP 401/403).
+    Auth,
+    // A pushing remote stopped pushing.
+    Stale,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PollError {
+    pub kind: PollErrorKind,
+    pub message: String,
+    pub at: DateTime<Utc>,
+}
+
+impl PollError {
+    fn new(kind: PollErrorKind, message: String) -> Self {
+        Self { kind, message, at: Utc::now() }
+    }
+
+    /// Classifies a failed request by walking its error chain: reqwest only
+    /// tells timeouts and connection errors apart, the underlying hyper and
+    /// io errors say whether it was DNS, a refused connection or TLS.
+    fn from_request(e: &reqwest::Error) -> Self {
+        let mut kind = if e.is_timeout() { PollErrorKind::Timeout } else { PollErrorKind::Connect };
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(e);
+        while let Some(cause) = source {
+            let text = cause.to_string();
+            let lower = text.to_lowercase();
+            if cause.downcast_ref::<std::io::Error>().is_some_and(|io| io.kind() == std::io::ErrorKind::ConnectionRefused) {
+                kind = PollErrorKind::ConnectionRefused;
+            } else if lower.contains("dns error") || lower.contains("failed to lookup address") {
+                kind = PollErrorKind::Dns;
+            } else if lower.contains("certificate") || lower.contains("tls") || lower.contains("ssl") {
+                kind = PollErrorKind::Tls;
+            }
+            if !message.ends_with(&text) {
+                message = format!("{}: {}", message, text);
+            }
+            source = cause.source();
+        }
+        Self::new(kind, message)
+    }
+}
+
+/// A successful poll (or push).
+struct Fetched {
+    data: Value,
+    latency_ms: Option<u64>,
+    clock_skew_secs: Option<f64>,
+}
+
+/// Metrics pushed to /api/ingest by a pylon in push mode.
+pub struct Push {
+    pub ip: String,
+    pub port: u16,
+    // The pylon id in `data`, checked against the credential that authenticated the push.
+    pub id: Option<String>,
+    pub data: Value,
+}
+
+pub type IngestSender = mpsc::UnboundedSender<Push>;
+
+/// Missed pushes after which a pushing remote counts as failing.
+const PUSH_GRACE_INTERVALS: u64 = 2;
+/// Seconds without a push after which a remote that is not configured is no
+/// longer tracked as pushing, and is then removed like a deleted remote.
+const PUSH_EXPIRY_SECS: u64 = 3600;
+
+/// Seconds between the remote's `timestamp` and `reference` (local time).
+fn clock_skew(data: &Value, reference: DateTime<Utc>) -> Option<f64> {
+    data.get("timestamp").and_then(|ts| ts.as_str())
+        .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        .map(|ts| (ts.with_timezone(&Utc) - reference).num_milliseconds() as f64 / 1000.0)
+}
+
+/// Series recorded into the history store for each remote, with the JSON
+/// pointer of the value in the remote's /api/metrics response. The names
+/// match `PolledMetrics::history_values`.
+const REMOTE_SERIES: [(&str, &str); 12] = [
+    ("cpu_usage", "/polled/cpu_usage"),
+    ("used_ram", "/polled/used_ram"),
+    ("available_ram", "/polled/available_ram"),
+    ("network_received", "/polled/network_received"),
+    ("network_transmitted", "/polled/network_transmitted"),
+    ("load_average_one", "/polled/load_average/one"),
+    ("load_average_five", "/polled/load_average/five"),
+    ("load_average_fifteen", "/polled/load_average/fifteen"),
+    ("swap_used", "/polled/swap_used"),
+    ("swap_total", "/polled/swap_total"),
+    ("disk_free", "/polled/disk_free"),
+    ("disk_usage_percent", "/polled/disk_usage_percent"),
+];
+
+/// The key a remote is tracked under: its pylon id once known, else "ip:port".
+pub fn remote_key(remote: &RemotePylonConfig) -> String {
+    remote.id.clone().unwrap_or_else(|| config_manager::address(remote))
+}
+
+/// The history series name of a remote's metric, e.g. "remote:<pylon id>:cpu_usage".
+pub fn remote_series(key: &str, metric: &str) -> String {
+    format!("remote:{}:{}", key, metric)
+}
+
+/// Records a successful poll's metrics into the history store.
+fn record_remote_history(history: &SharedHistory, key: &str, data: &Value, ts: i64) {
+    let values: Vec<(String, f64)> = REMOTE_SERIES.iter()
+        .filter_map(|(name, pointer)| {
+            data.pointer(pointer).and_then(|v| v.as_f64()).map(|v| (remote_series(key, name), v))
+        })
+        .collect();
+    history.lock().unwrap().record(ts, values);
+}
+
+/// Gaps in a remote's history shorter than this are not backfilled.
+const BACKFILL_MIN_GAP: i64 = 30;
+
+#[derive(Deserialize)]
+struct HistoryResponse {
+    tier: String,
+    points: Vec<Point>,
+}
+
+/// Fetches the history a remote recorded itself between `from` and `to` (while
+/// we could not reach it) and merges it into ours, so network partitions leave
+/// no gaps. Long outages come back at the remote's rollup resolution.
+async fn backfill_remote(client: Client, remote: RemotePylonConfig, key: String, from: i64, to: i64, history: SharedHistory) {
+    let mut filled = 0;
+    for (name, _) in REMOTE_SERIES.iter() {
+        let url = format!("http://{}:{}/api/history?metric={}&from={}&to={}", remote.ip, remote.port, name, from, to);
+        let resp = client.get(&url)
+            .bearer_auth(remote.token.clone())
+            .timeout(Duration::from_secs(30))
+            .send()
+            .await;
+        let body = match resp {
+            Ok(resp) if resp.status().is_success() => resp.json::<HistoryResponse>().await,
+            Ok(resp) => {
+                println!("Remote {} refused history backfill: HTTP {}", key, resp.status());
+                return;
+            },
+            Err(e) => {
+                println!("Error backfilling history from remote {}: {}", key, e);
+                return;
+            }
+        };
+        match body {
+            Ok(body) => {
+                if let Some(tier) = Tier::from_name(&body.tier) {
+                    filled += body.points.len();
+                    history.lock().unwrap().backfill(&remote_series(&key, name), tier, &body.points);
+                }
+            },
+            Err(e) => {
+                println!("Invalid history response from remote {}: {}", key, e);
+                return;
+            }
+        }
+    }
+    println!("Backfilled {} points of history for remote {}", filled, key);
+}
+
+/// Remotes polled at the same time unless `remote_concurrency` says otherwise.
+const DEFAULT_CONCURRENCY: usize = 16;
+const DEFAULT_POLL_INTERVAL: u64 = 10;
+const DEFAULT_TIMEOUT: u64 = 5;
+const DEFAULT_MAX_BACKOFF: u64 = 300;
+const DEFAULT_REMOVED_GRACE: i64 = 300;
+
+/// Effective polling settings of one remote: its own, else `[remote_defaults]`.
+struct PollSettings {
+    interval: u64,
+    timeout: u64,
+    retry_backoff: u64,
+    max_backoff: u64,
+}
+
+impl PollSettings {
+    fn resolve(remote: &RemotePylonConfig, defaults: &RemoteDefaults) -> Self {
+        let interval = remote.poll_interval.or(defaults.poll_interval).unwrap_or(DEFAULT_POLL_INTERVAL).max(1);
+        Self {
+            interval,
+            timeout: remote.timeout.or(defaults.timeout).unwrap_or(DEFAULT_TIMEOUT).max(1),
+            retry_backoff: remote.retry_backoff.or(defaults.retry_backoff).unwrap_or(interval).max(1),
+            max_backoff: defaults.max_backoff.unwrap_or(DEFAULT_MAX_BACKOFF),
+        }
+    }
+
+    /// Delay before the next poll. Offline remotes back off exponentially:
+    /// retry_backoff, then twice that, four times, ... up to max_backoff.
+    fn next_delay(&self, offline: bool, consecutive_failures: u32) -> u64 {
+        if offline {
+            let factor = 1u64 << consecutive_failures.saturating_sub(1).min(16);
+            self.retry_backoff.saturating_mul(factor).min(self.max_backoff.max(self.retry_backoff))
+        } else {
+            self.interval
+        }
+    }
+}
+
+/// Fetches a remote's /api/metrics, timing the request and comparing the
+/// remote's clock with ours.
+async fn fetch_metrics(client: Client, remote: RemotePylonConfig, timeout: u64) -> Result<Fetched, PollError> {
+    let url = format!("http://{}:{}/api/metrics", remote.ip, remote.port);
+    let sent_at = Utc::now();
+    let started = Instant::now();
+    let resp = client.get(&url)
+        .bearer_auth(remote.token.clone())
+        .timeout(Duration::from_secs(timeout))
+        .send()
+        .await
+        .map_err(|e| PollError::from_request(&e))?;
+    let status = resp.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return Err(PollError::new(PollErrorKind::Auth, format!("HTTP {} (check the token)", status)));
+    }
+    if !status.is_success() {
+        return Err(PollError::new(PollErrorKind::HttpStatus, format!("HTTP {}", status)));
+    }
+    let latency = started.elapsed();
+    let body = resp.bytes().await.map_err(|e| PollError::from_request(&e))?;
+    let data: Value = serde_json::from_slice(&body)
+        .map_err(|e| PollError::new(PollErrorKind::BadJson, format!("invalid JSON: {}", e)))?;
+
+    // The remote stamped its response roughly half way through the round trip.
+    let midpoint = sent_at + chrono::Duration::from_std(latency / 2).unwrap_or_default();
+    let clock_skew_secs = clock_skew(&data, midpoint);
+    Ok(Fetched { data, latency_ms: Some(latency.as_millis() as u64), clock_skew_secs })
+}
+
+/// A random offset of up to ±10% of `secs`, so remotes added together do
+/// not stay in lockstep.
+fn jitter(secs: u64) -> Duration {
+    let spread = secs as f64 * 0.1;
+    Duration::from_secs_f64((secs as f64 + rand::thread_rng().gen_range(-spread..=spread)).max(1.0))
+}
+
+/// State of the remote poller, owned by its loop.
+struct RemotePoller {
+    client: Client,
+    config_arc: Arc<std::sync::RwLock<Config>>,
+    remote_statuses: Arc<Mutex<HashMap<String, RemoteStatus>>>,
+    history: SharedHistory,
+    // Peers advertised by remotes (see discovery.rs).
+    discovery: SharedDiscovery,
+    // Debounces each remote's online state (see flapping.rs).
+    trackers: HashMap<String, StateTracker>,
+    // When each remote is polled next (or, for pushing remotes, checked for staleness).
+    next_due: HashMap<String, Instant>,
+    // Remotes that push to /api/ingest, with when they last did.
+    pushed: HashMap<String, (RemotePylonConfig, Instant)>,
+}
+
+impl RemotePoller {
+    /// Configured remotes, keyed by pylon id (or "ip:port" until the id is
+    /// known). Entries reaching the same pylon by different addresses are
+    /// polled once.
+    fn all_remotes(&self) -> Vec<(String, RemotePylonConfig)> {
+        let mut all_remotes: Vec<(String, RemotePylonConfig)> = Vec::new();
+        let config = self.config_arc.read().unwrap();
+        for remote in config.remote_pylons.iter().flatten() {
+            let key = remote_key(remote);
+            if !all_remotes.iter().any(|(k, _)| *k == key) {
+                all_remotes.push((key, remote.clone()));
+            }
+        }
+        all_remotes
+    }
+
+    fn settings(&self, remote: &RemotePylonConfig) -> PollSettings {
+        let defaults = self.config_arc.read().unwrap().remote_defaults.clone().unwrap_or_default();
+        PollSettings::resolve(remote, &defaults)
+    }
+
+    /// Moves a remote's state and history from `key` to the pylon id it
+    /// reported, and records the id in config.toml so it survives restarts
+    /// and new addresses.
+    fn learn_id(&mut self, key: String, mut remote: RemotePylonConfig, id: String) -> (String, RemotePylonConfig) {
+        println!("Remote {} is pylon {}", key, id);
+        {
+            let mut statuses = self.remote_statuses.lock().unwrap();
+            if let Some(status) = statuses.remove(&key) {
+                statuses.insert(id.clone(), status);
+            }
+        }
+        if let Some(tracker) = self.trackers.remove(&key) {
+            self.trackers.insert(id.clone(), tracker);
+        }
+        if let Some(due) = self.next_due.remove(&key) {
+            self.next_due.insert(id.clone(), due);
+        }
+        {
+            let mut history = self.history.lock().unwrap();
+            let prefix = remote_series(&key, "");
+            for name in history.series_names().into_iter().filter(|name| name.starts_with(&prefix)) {
+                history.rename_series(&name, &remote_series(&id, &name[prefix.len()..]));
+            }
+            history.rename_entity(&key, &id);
+        }
+        remote.id = Some(id.clone());
+
+        let address = config_manager::address(&remote);
+        let mut config = self.config_arc.write().unwrap();
+        let mut changed = false;
+        for configured in config.remote_pylons.iter_mut().flatten() {
+            if config_manager::address(configured) == address && configured.id.as_ref() != Some(&id) {
+                configured.id = Some(id.clone());
+                changed = true;
+            }
+        }
+        if changed {
+            if let Err(e) = save_config(&config) {
+                println!("Failed to save config.toml: {}", e);
+            }
+        }
+        (id, remote)
+    }
+
+    /// Handles the outcome of one poll: updates the remote's status and
+    /// history, discovers its peers and schedules its next poll.
+    fn apply(&mut self, key: String, remote: RemotePylonConfig, result: Result<Fetched, PollError>) {
+        let reported = result.as_ref().ok()
+            .and_then(|fetched| fetched.data.get("id"))
+            .and_then(|id| id.as_str())
+            .filter(|id| !id.is_empty() && *id != key)
+            .map(String::from);
+        let (key, remote) = match reported {
+            Some(id) => self.learn_id(key, remote, id),
+            None => (key, remote),
+        };
+        let previous = self.remote_statuses.lock().unwrap().get(&key).cloned();
+        let mut status = RemoteStatus {
+            ip: remote.ip.clone(),
+            port: remote.port,
+            id: remote.id.clone(),
+            last_seen: None,
+            data: None,
+            online: false,
+            name: remote.name.clone(),
+            location: remote.location.clone(),
+            description: remote.description.clone(),
+            transitions: 0,
+            flapping: false,
+            consecutive_failures: 0,
+            last_error: previous.as_ref().and_then(|p| p.last_error.clone()),
+            latency_ms: None,
+            version: previous.as_ref().and_then(|p| p.version.clone()),
+            clock_skew_secs: previous.as_ref().and_then(|p| p.clock_skew_secs),
+            pushed: self.pushed.contains_key(&key),
+            // A poll that was in flight when the remote was removed does not revive it.
+            removed_at: previous.as_ref().and_then(|p| p.removed_at),
+        };
+        match result {
+            Ok(fetched) => {
+                let json_data = fetched.data;
+                // Peer discovery: offer the remotes this remote polls (see discovery.rs).
+                if let Some(array) = json_data.get("remote_pylons").and_then(|peers| peers.as_array()) {
+                    let mut discovery = self.discovery.lock().unwrap();
+                    for peer_val in array {
+                        if let Ok(mut peer_config) = serde_json::from_value::<RemotePylonConfig>(peer_val.clone()) {
+                            // Older pylons advertised their tokens; they are not taken over.
+                            peer_config.token.clear();
+                            discovery.advertise(&self.config_arc, peer_config, &key);
+                        }
+                    }
+                }
+                discovery::mark_seen(&self.config_arc, &key);
+                status.version = json_data.get("version").and_then(|v| v.as_str()).map(String::from);
+                status.latency_ms = fetched.latency_ms;
+                status.clock_skew_secs = fetched.clock_skew_secs;
+                status.data = Some(json_data);
+                status.online = true;
+                status.last_seen = Some(Utc::now());
+            },
+            Err(e) => {
+                println!("Remote {} failed: {}", key, e.message);
+                status.last_error = Some(e);
+            }
+        }
+
+        let now = Utc::now().timestamp();
+        let reachable = status.online;
+        let tracker = self.trackers.entry(key.clone()).or_default();
+        // Back from a failed poll (or we restarted): fetch what the remote recorded meanwhile.
+        let returning = tracker.last_check_failed() || previous.is_none();
+        let hysteresis = self.config_arc.read().unwrap().hysteresis.clone().unwrap_or_default();
+        status.online = tracker.observe(reachable, now, &hysteresis);
+        status.transitions = tracker.transitions();
+        status.flapping = tracker.flapping();
+        status.consecutive_failures = tracker.consecutive_failures();
+        if !reachable {
+            if let Some(previous) = previous {
+                status.last_seen = previous.last_seen;
+                // Still online within the hysteresis: keep showing the last metrics.
+                if status.online {
+                    status.data = previous.data;
+                    status.latency_ms = previous.latency_ms;
+                }
+            }
+        }
+        if let Some(data) = status.data.as_ref().filter(|_| reachable) {
+            // Pushing remotes usually cannot be reached for a backfill.
+            if returning && !status.pushed {
+                let last = self.history.lock().unwrap().latest_ts(&remote_series(&key, "cpu_usage"));
+                if let Some(last) = last.filter(|last| now - last > BACKFILL_MIN_GAP) {
+                    tokio::spawn(backfill_remote(self.client.clone(), remote.clone(), key.clone(), last + 1, now - 1, Arc::clone(&self.history)));
+                }
+            }
+            record_remote_history(&self.history, &key, data, now);
+        }
+        self.history.lock().unwrap().record_state(&key, now, status.online);
+        let delay = self.settings(&remote).next_delay(!status.online, status.consecutive_failures);
+        self.next_due.insert(key.clone(), Instant::now() + jitter(delay));
+        self.remote_statuses.lock().unwrap().insert(key, status);
+    }
+
+    /// Handles metrics pushed by a remote in push mode.
+    fn receive_push(&mut self, push: Push) {
+        let interval = push.data.get("push_interval").and_then(|v| v.as_u64()).unwrap_or(DEFAULT_POLL_INTERVAL).max(1);
+        let text = |field: &str| push.data.get(field).and_then(|v| v.as_str()).map(String::from);
+        let remote = RemotePylonConfig {
+            ip: push.ip.clone(),
+            port: push.port,
+            token: String::new(),
+            id: push.id.clone(),
+            name: text("name"),
+            location: text("location"),
+            description: text("description"),
+            poll_interval: Some(interval),
+            timeout: None,
+            retry_backoff: Some(interval),
+            discovered: None,
+            last_seen: None,
+        };
+        let key = remote_key(&remote);
+        if !self.pushed.contains_key(&key) {
+            println!("Receiving pushed metrics from {}", key);
+        }
+        self.pushed.insert(key.clone(), (remote.clone(), Instant::now()));
+        let clock_skew_secs = clock_skew(&push.data, Utc::now());
+        self.apply(key.clone(), remote, Ok(Fetched { data: push.data, latency_ms: None, clock_skew_secs }));
+        self.next_due.insert(key, Instant::now() + Duration::from_secs(interval * PUSH_GRACE_INTERVALS));
+    }
+
+    /// Reconciles the statuses with the configured and pushing remotes: a
+    /// remote that is neither is marked removed, and forgotten once
+    /// `removed_remote_grace` has passed. One that comes back is unmarked.
+    fn reconcile(&mut self) {
+        let mut active: HashSet<String> = self.all_remotes().into_iter().map(|(key, _)| key).collect();
+        active.extend(self.pushed.keys().cloned());
+        // Remotes keyed by "ip:port" until they report their id, under which they are configured.
+        let addresses: HashSet<String> = self.config_arc.read().unwrap().remote_pylons.iter().flatten()
+            .map(config_manager::address)
+            .collect();
+        let grace = self.config_arc.read().unwrap().removed_remote_grace.map(|secs| secs as i64).unwrap_or(DEFAULT_REMOVED_GRACE);
+        let now = Utc::now();
+
+        let mut forgotten = Vec::new();
+        {
+            let mut statuses = self.remote_statuses.lock().unwrap();
+            for (key, status) in statuses.iter_mut() {
+                let configured = active.contains(key) || addresses.contains(&format!("{}:{}", status.ip, status.port));
+                match (configured, status.removed_at) {
+                    (true, Some(_)) => {
+                        println!("Remote {} is configured again", key);
+                        status.removed_at = None;
+                    },
+                    (false, None) => {
+                        println!("Remote {} was removed from the config; forgetting it in {}s", key, grace);
+                        status.removed_at = Some(now);
+                    },
+                    (false, Some(removed_at)) if (now - removed_at).num_seconds() >= grace => forgotten.push(key.clone()),
+                    _ => {}
+                }
+            }
+            for key in forgotten.iter() {
+                statuses.remove(key);
+            }
+        }
+        for key in forgotten {
+            println!("Forgot removed remote {}", key);
+            self.trackers.remove(&key);
+            self.next_due.remove(&key);
+        }
+    }
+
+    /// Counts a missed push as a failed check for every pushing remote that
+    /// is overdue. Remotes that are also configured for polling go back to
+    /// being polled instead, and the others are dropped after
+    /// `PUSH_EXPIRY_SECS` of silence.
+    fn check_pushes(&mut self, now: Instant) {
+        let polled: HashSet<String> = self.all_remotes().into_iter().map(|(key, _)| key).collect();
+        let overdue: Vec<(String, RemotePylonConfig, Instant)> = self.pushed.iter()
+            .filter(|(key, _)| self.next_due.get(*key).is_some_and(|due| *due <= now))
+            .map(|(key, (remote, last_push))| (key.clone(), remote.clone(), *last_push))
+            .collect();
+        for (key, remote, last_push) in overdue {
+            if polled.contains(&key) {
+                println!("Remote {} stopped pushing; polling it again", key);
+                self.pushed.remove(&key);
+                continue;
+            }
+            if last_push.elapsed() >= Duration::from_secs(PUSH_EXPIRY_SECS) {
+                println!("Remote {} stopped pushing {}s ago; no longer tracking it", key, last_push.elapsed().as_secs());
+                self.pushed.remove(&key);
+                self.next_due.remove(&key);
+                continue;
+            }
+            let error = PollError::new(PollErrorKind::Stale, format!("no metrics pushed for {}s", last_push.elapsed().as_secs()));
+            self.apply(key, remote, Err(error));
+        }
+    }
+}
+
+/// Polls every remote concurrently on its own schedule (`poll_interval`,
+/// jittered, backing off exponentially while offline), with at most `remote_concurrency` requests in flight, so dead
+/// remotes waiting out their timeout do not delay the others.
+/// Every poll is recorded into the history store: metrics of online remotes
+/// as series, and online/offline changes as transitions.
 /// Also performs peer discovery by reading the "remote_pylons" field returned
-/// by remote servers and adding new ones to the polling list.
+/// by remote servers (see discovery.rs).
+/// Metrics pushed to /api/ingest arrive on `ingest` and are handled like
+/// polls; a pushing remote is not polled while its pushes keep arriving.
 pub async fn poll_remote_pylons(
     config_arc: Arc<std::sync::RwLock<Config>>,
     remote_statuses: Arc<Mutex<HashMap<String, RemoteStatus>>>,
+    history: SharedHistory,
+    discovery: SharedDiscovery,
+    mut ingest: mpsc::UnboundedReceiver<Push>,
     mut shutdown: watch::Receiver<bool>
 ) {
-    let client = Client::new();
-    // Discovered peers (not in the initial config)
-    let mut discovered_peers: Vec<crate::config_manager::RemotePylonConfig> = Vec::new();
+    let mut poller = RemotePoller {
+        client: Client::new(),
+        config_arc,
+        remote_statuses,
+        history,
+        discovery,
+        trackers: HashMap::new(),
+        next_due: HashMap::new(),
+        pushed: HashMap::new(),
+    };
+    let mut in_flight = FuturesUnordered::new();
+    let mut polling: HashSet<String> = HashSet::new();
+    let mut ticker = interval(Duration::from_secs(1));
     loop {
         tokio::select! {
             _ = shutdown.changed() => {
@@ -41,75 +619,34 @@ pub async fn poll_remote_pylons(
                     break;
                 }
             },
-            _ = async {
-                // Build list of all remotes to poll: config.remote_pylons + discovered_peers (deduplicated)
-                let mut all_remotes: Vec<crate::config_manager::RemotePylonConfig> = Vec::new();
-                {
-                    let config = config_arc.read().unwrap().clone();
-                    if let Some(remote_list) = config.remote_pylons {
-                        all_remotes.extend(remote_list);
-                    }
-                }
-                // Add discovered peers that are not already in all_remotes.
-                for peer in discovered_peers.iter() {
-                    let key = format!("{}:{}", peer.ip, peer.port);
-                    if !all_remotes.iter().any(|r| format!("{}:{}", r.ip, r.port) == key) {
-                        all_remotes.push(peer.clone());
+            Some((key, remote, result)) = in_flight.next() => {
+                polling.remove(&key);
+                poller.apply(key, remote, result);
+            },
+            Some(push) = ingest.recv() => {
+                poller.receive_push(push);
+            },
+            _ = ticker.tick() => {
+                let limit = poller.config_arc.read().unwrap().remote_concurrency.unwrap_or(DEFAULT_CONCURRENCY).max(1);
+                let now = Instant::now();
+                poller.check_pushes(now);
+                poller.reconcile();
+                for (key, remote) in poller.all_remotes() {
+                    if in_flight.len() >= limit {
+                        break;
                     }
-                }
-                
-                for remote in all_remotes {
-                    let key = format!("{}:{}", remote.ip, remote.port);
-                    let url = format!("http://{}:{}/api/metrics", remote.ip, remote.port);
-                    let req = client.get(&url)
-                        .bearer_auth(remote.token.clone())
-                        .timeout(Duration::from_secs(5))
-                        .send()
-                        .await;
-                    let mut status = RemoteStatus {
-                        ip: remote.ip.clone(),
-                        port: remote.port,
-                        last_seen: None,
-                        data: None,
-                        online: false,
-                        name: remote.name.clone(),
-						location: remote.location.clone(),
-						description: remote.description.clone()
-                    };
-                    match req {
-                        Ok(resp) => {
-                            if resp.status().is_success() {
-                                if let Ok(json_data) = resp.json::<Value>().await {
-                                    status.data = Some(json_data.clone());
-                                    status.online = true;
-                                    status.last_seen = Some(Utc::now());
-                                    // Peer discovery: if the remote data contains "remote_pylons", add them
-                                    if let Some(peers) = json_data.get("remote_pylons") {
-                                        if let Some(array) = peers.as_array() {
-                                            for peer_val in array {
-                                                if let Ok(peer_config) = serde_json::from_value::<crate::config_manager::RemotePylonConfig>(peer_val.clone()) {
-                                                    let peer_key = format!("{}:{}", peer_config.ip, peer_config.port);
-                                                    if !discovered_peers.iter().any(|p| format!("{}:{}", p.ip, p.port) == peer_key) &&
-                                                       peer_key != key { // avoid self
-                                                        discovered_peers.push(peer_config);
-                                                        println!("Discovered new peer: {}", peer_key);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            println!("Error connecting to remote {}: {}", key, e);
-                        }
+                    let settings = poller.settings(&remote);
+                    // New remotes start within the first tenth of their interval.
+                    let due = *poller.next_due.entry(key.clone())
+                        .or_insert_with(|| now + jitter(settings.interval) / 10);
+                    if due > now || polling.contains(&key) || poller.pushed.contains_key(&key) {
+                        continue;
                     }
-                    let mut statuses = remote_statuses.lock().unwrap();
-                    statuses.insert(key, status);
+                    polling.insert(key.clone());
+                    let fetch = fetch_metrics(poller.client.clone(), remote.clone(), settings.timeout);
+                    in_flight.push(async move { (key, remote, fetch.await) });
                 }
-            } => {}
+            }
         }
-        sleep(Duration::from_secs(10)).await;
     }
 }
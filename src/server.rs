@@ -5,7 +5,7 @@ use actix_session::{Session, SessionMiddleware};
 use actix_session::storage::CookieSessionStore;
 use actix_web::cookie::Key;
 use serde::Deserialize;
-use serde_json::json;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex, RwLock};
 use mime_guess::from_path;
@@ -24,6 +24,19 @@ pub struct AppState {
     pub config: Arc<RwLock<crate::config_manager::Config>>,
     pub system_data: Arc<Mutex<SystemData>>,
     pub remote_statuses: Arc<Mutex<HashMap<String, RemoteStatus>>>,
+    pub history: crate::history::SharedHistory,
+    pub alerts: crate::alerts::SharedAlerts,
+    // Metrics pushed to /api/ingest, handed to the remote poller.
+    pub ingest: crate::remote::IngestSender,
+    pub discovery: crate::discovery::SharedDiscovery,
+    // Pylons heard on the local network (see lan.rs).
+    pub lan: crate::lan::SharedLan,
+    // Pairing codes and the credentials issued to paired pylons (see pairing.rs).
+    pub pairing: crate::pairing::SharedPairing,
+    // The federation tree served to the dashboard (see federation.rs).
+    pub federation: crate::federation::SharedFederationCache,
+    // The port the server is bound to, which may differ from `local_port`.
+    pub port: u16,
 }
 
 /// Embed the contents of the `static/` folder into the binary.
@@ -119,8 +132,16 @@ async fn index() -> impl Responder {
       </div>
       <div class="services" id="servicesStatus"></div>
     </div>
+    <div class="card" id="alertsCard">
+      <h2>Alerts 🚨</h2>
+      <div id="alertsList"></div>
+    </div>
     <h2>Remote Pylons 🌐</h2>
     <div id="remoteContainer"></div>
+    <div class="card" id="federationCard" style="display:none;">
+      <h2>Federation 🌳</h2>
+      <div id="federationTree"></div>
+    </div>
     <div class="card" id="networkCard">
       <h2>Network Throughput 🌐</h2>
       <div class="network-chart-container">
@@ -164,7 +185,7 @@ async fn login(
     session: Session,
 ) -> impl Responder {
     let config = data.config.read().unwrap();
-    if login_req.token == config.token {
+    if !config.token.is_empty() && login_req.token == config.token {
         session.insert("admin_authenticated", true).unwrap();
         HttpResponse::Ok().json(json!({"status": "logged in"}))
     } else {
@@ -197,6 +218,68 @@ async fn admin_content(session: Session) -> impl Responder {
           <tbody></tbody>
         </table>
       </div>
+      <div class="card" id="listeningPortsCard">
+        <h2>Listening Ports 🔌</h2>
+        <div id="tcpStates"></div>
+        <table id="listeningPortsTable">
+          <thead>
+            <tr><th>Proto</th><th>Address</th><th>Port</th><th>PID</th><th>Process</th></tr>
+          </thead>
+          <tbody></tbody>
+        </table>
+      </div>
+      <div class="card" id="certificatesCard">
+        <h2>TLS Certificates 🔒</h2>
+        <table id="certificatesTable">
+          <thead>
+            <tr><th>Source</th><th>Subject</th><th>Issuer</th><th>Expires In</th></tr>
+          </thead>
+          <tbody></tbody>
+        </table>
+      </div>
+      <div class="card" id="logWatchCard">
+        <h2>Log Watch 📜</h2>
+        <div id="logWatchDetails"></div>
+      </div>
+      <div class="card" id="silencesCard">
+        <h2>Silences 🔕</h2>
+        <form id="silenceForm">
+          <input type="text" id="silenceRule" placeholder="Rule (optional)">
+          <input type="text" id="silencePylon" placeholder="Pylon (optional)">
+          <input type="text" id="silenceDuration" placeholder="Duration, e.g. 2h" required>
+          <input type="text" id="silenceComment" placeholder="Comment (optional)">
+          <button type="submit">Silence</button>
+        </form>
+        <h3>Active Alerts</h3>
+        <ul id="ackList"></ul>
+        <h3>Active Silences</h3>
+        <ul id="silenceList"></ul>
+      </div>
+      <div class="card" id="pairingCard">
+        <h2>Pairing 🤝</h2>
+        <button id="pairingCodeButton">Generate Pairing Code</button>
+        <div id="pairingCodes"></div>
+        <form id="pairingForm">
+          <input type="text" id="pairingAddress" placeholder="Other pylon (ip:port)" required>
+          <input type="text" id="pairingCode" placeholder="Pairing code" required>
+          <button type="submit">Pair</button>
+        </form>
+        <h3>Paired Pylons</h3>
+        <ul id="pairedList"></ul>
+      </div>
+      <div class="card" id="lanCard">
+        <h2>Pylons on the Local Network 📡</h2>
+        <div id="lanStatus"></div>
+        <ul id="lanPylonList"></ul>
+      </div>
+      <div class="card" id="discoveryCard">
+        <h2>Discovered Peers 🔭</h2>
+        <div id="discoveryMode"></div>
+        <h3>Awaiting Approval</h3>
+        <ul id="pendingPeerList"></ul>
+        <h3>Rejected</h3>
+        <ul id="rejectedPeerList"></ul>
+      </div>
       <div class="card" id="managePylonsCard">
         <h2>Manage Remote Pylons</h2>
         <form id="pylonForm">
@@ -218,51 +301,384 @@ async fn admin_content(session: Session) -> impl Responder {
 
 /// GET /api/metrics
 ///
-/// Returns local metrics as JSON.
+/// Returns local metrics as JSON to peers and to the unlocked dashboard.
 #[get("/api/metrics")]
-async fn metrics(data: web::Data<AppState>, _req: HttpRequest) -> impl Responder {
+async fn metrics(data: web::Data<AppState>, req: HttpRequest, session: Session) -> impl Responder {
+    if revoked(&data, &req) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Credential revoked"}));
+    }
+    if !peer_authorized(&data, &req) && !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
     let sys_data = data.system_data.lock().unwrap();
     let config = data.config.read().unwrap();
+    HttpResponse::Ok().json(local_metrics(&sys_data, &config))
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+fn bearer(req: &HttpRequest) -> Option<&str> {
+    req.headers().get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Whether the request presents this pylon's token or an active pairing credential.
+fn peer_authorized(data: &AppState, req: &HttpRequest) -> bool {
+    bearer(req).filter(|token| !token.is_empty()).is_some_and(|token| {
+        token == data.config.read().unwrap().token || data.pairing.lock().unwrap().is_active(token)
+    })
+}
+
+/// Whether the session was unlocked with the admin key (see /api/login).
+fn is_admin(session: &Session) -> bool {
+    matches!(session.get::<bool>("admin_authenticated"), Ok(Some(true)))
+}
+
+/// Whether the request presents a pairing credential we revoked.
+fn revoked(data: &AppState, req: &HttpRequest) -> bool {
+    bearer(req).is_some_and(|token| data.pairing.lock().unwrap().is_revoked(token))
+}
+
+/// The metrics of this pylon as served by /api/metrics and pushed upstream.
+pub fn local_metrics(sys_data: &SystemData, config: &crate::config_manager::Config) -> Value {
     let local_name = config.name.clone().unwrap_or_else(|| "Local Pylon".to_string());
     let local_description = config.description.clone().unwrap_or_else(|| "Sorry, no description was provided for this Pylon.".to_string());
     let local_location = config.location.clone().unwrap_or_else(|| "Unknown Location".to_string());
     
-    let cached_json = serde_json::to_value(&sys_data.cached).unwrap();
-    let filtered_cached = if let Some(allowed) = &config.software_versions {
-        if let Some(obj) = cached_json.as_object() {
-            let version_keys = ["os_version", "apache_version", "php_version", "mariadb_version", "rust_version", "node_version", "npm_version"];
-            let filtered: serde_json::Map<String, serde_json::Value> = obj.iter().filter(|(k, _)| {
-                if version_keys.contains(&k.as_str()) {
-                    allowed.contains(k)
-                } else {
-                    true
-                }
-            }).map(|(k,v)| (k.clone(), v.clone())).collect();
-            serde_json::Value::Object(filtered)
+    let mut response = sys_data.to_json();
+    response["name"] = json!(local_name);
+    response["description"] = json!(local_description);
+    response["location"] = json!(local_location);
+    response["id"] = json!(config.id);
+    response["version"] = json!(PYLON_VERSION);
+    // Lets pollers measure the clock skew between them and us.
+    response["timestamp"] = json!(chrono::Utc::now().to_rfc3339());
+    // Advertised for peer discovery; the tokens stay private.
+    response["remote_pylons"] = json!(remotes_without_tokens(config));
+    response
+}
+
+/// `remote_pylons` with the tokens blanked, for serving outside config.toml.
+fn remotes_without_tokens(config: &crate::config_manager::Config) -> Option<Vec<crate::config_manager::RemotePylonConfig>> {
+    config.remote_pylons.as_ref().map(|configured| configured.iter()
+        .map(|remote| crate::config_manager::RemotePylonConfig { token: String::new(), ..remote.clone() })
+        .collect())
+}
+
+/// GET /api/federate?depth=&visited=
+///
+/// Returns the federation tree below this pylon: its own metrics plus the
+/// statuses of its remotes, each expanded with the remote's own tree.
+/// Peers presenting a token get a freshly built tree; the dashboard shares
+/// a cached one, so it cannot make the whole tree fan out on every call.
+#[get("/api/federate")]
+async fn federate(data: web::Data<AppState>, req: HttpRequest, session: Session, query: web::Query<crate::federation::FederateQuery>) -> impl Responder {
+    if revoked(&data, &req) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Credential revoked"}));
+    }
+    let tree = if peer_authorized(&data, &req) {
+        crate::federation::federate(&data.config, &data.system_data, &data.remote_statuses, query.into_inner()).await
+    } else if is_admin(&session) {
+        crate::federation::cached_federate(&data.federation, &data.config, &data.system_data, &data.remote_statuses).await
+    } else {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    };
+    HttpResponse::Ok().json(tree)
+}
+
+/// POST /api/ingest
+///
+/// Receives the metrics of a pylon that has this pylon as its `upstream`
+/// (push mode). Requires `Authorization: Bearer <token>` with this pylon's
+/// token. The pylon is keyed by its address and the `port` it reports, so it
+/// merges with a polled entry for the same pylon.
+#[post("/api/ingest")]
+async fn ingest(data: web::Data<AppState>, req: HttpRequest, body: web::Bytes) -> impl Responder {
+    if !peer_authorized(&data, &req) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
+    let pushed: Value = match serde_json::from_slice(&body) {
+        Ok(pushed) => pushed,
+        Err(e) => return HttpResponse::BadRequest().json(json!({"error": format!("invalid JSON: {}", e)})),
+    };
+    let port = pushed.get("port").and_then(|port| port.as_u64()).and_then(|port| u16::try_from(port).ok());
+    let (Some(port), Some(peer)) = (port, req.peer_addr()) else {
+        return HttpResponse::BadRequest().json(json!({"error": "missing 'port'"}));
+    };
+    let id = pushed.get("id").and_then(|id| id.as_str()).filter(|id| !id.is_empty()).map(String::from);
+    if !data.pairing.lock().unwrap().allows_push(bearer(&req).unwrap_or_default(), id.as_deref()) {
+        return HttpResponse::Forbidden().json(json!({"error": "pylon id does not match the credential"}));
+    }
+    let _ = data.ingest.send(crate::remote::Push { ip: peer.ip().to_string(), port, id, data: pushed });
+    HttpResponse::Ok().json(json!({"status": "received"}))
+}
+
+/// GET /api/admin/logs
+///
+/// Returns the watched log files with their counters and recent matching lines.
+#[get("/api/admin/logs")]
+async fn admin_logs(data: web::Data<AppState>, session: Session) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        let sys_data = data.system_data.lock().unwrap();
+        HttpResponse::Ok().json(&sys_data.logs)
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+/// GET /api/alerts
+///
+/// Returns the pending and firing alerts, the most recently resolved ones, the
+/// active silences and open maintenance windows, and any rules that failed to
+/// parse.
+#[get("/api/alerts")]
+async fn get_alerts(data: web::Data<AppState>, req: HttpRequest, session: Session) -> impl Responder {
+    if !peer_authorized(&data, &req) && !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
+    let alerts = data.alerts.lock().unwrap();
+    let mut active: Vec<_> = alerts.active.values().collect();
+    active.sort_by_key(|alert| alert.pending_since);
+    HttpResponse::Ok().json(json!({
+        "active": active,
+        "recent": alerts.recent,
+        "silences": alerts.silences,
+        "maintenance": alerts.maintenance,
+        "errors": alerts.errors,
+    }))
+}
+
+#[derive(Deserialize)]
+struct AckRequest {
+    id: String,
+    comment: Option<String>,
+}
+
+/// POST /api/admin/alerts/ack
+///
+/// Acknowledges an active alert so no notification is sent for it until it
+/// resolves.
+#[post("/api/admin/alerts/ack")]
+async fn ack_alert(data: web::Data<AppState>, session: Session, request: web::Json<AckRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        let request = request.into_inner();
+        match data.alerts.lock().unwrap().acknowledge(&request.id, request.comment) {
+            Some(alert) => HttpResponse::Ok().json(alert),
+            None => HttpResponse::NotFound().json(json!({"error": "No such active alert"})),
+        }
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+#[derive(Deserialize)]
+struct SilenceRequest {
+    pylon: Option<String>,
+    rule: Option<String>,
+    labels: Option<HashMap<String, String>>,
+    // e.g. "2h"
+    duration: String,
+    comment: Option<String>,
+}
+
+/// POST /api/admin/silences
+///
+/// Silences alerts matching the given pylon, rule and labels for a duration.
+#[post("/api/admin/silences")]
+async fn add_silence(data: web::Data<AppState>, session: Session, request: web::Json<SilenceRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        let request = request.into_inner();
+        let duration = match crate::config_manager::parse_duration(&request.duration) {
+            Some(duration) if duration > 0 => duration,
+            _ => return HttpResponse::BadRequest().json(json!({"error": "Invalid duration"})),
+        };
+        let silence = data.alerts.lock().unwrap()
+            .add_silence(request.pylon, request.rule, request.labels, duration, request.comment);
+        match silence {
+            Some(silence) => HttpResponse::Ok().json(silence),
+            None => HttpResponse::BadRequest().json(json!({"error": "Invalid duration"})),
+        }
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+#[derive(Deserialize)]
+struct RemoveSilenceRequest {
+    id: String,
+}
+
+/// POST /api/admin/silences/remove
+#[post("/api/admin/silences/remove")]
+async fn remove_silence(data: web::Data<AppState>, session: Session, request: web::Json<RemoveSilenceRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        if data.alerts.lock().unwrap().remove_silence(&request.id) {
+            HttpResponse::Ok().json(json!({"status": "removed"}))
         } else {
-            cached_json
+            HttpResponse::NotFound().json(json!({"error": "No such silence"}))
+        }
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+#[derive(Deserialize)]
+struct NotifierTestRequest {
+    name: Option<String>,
+}
+
+/// POST /api/admin/notifiers/test
+///
+/// Sends a test alert to the notifier named in the JSON body (or to every
+/// notifier when no name is given) and returns the result of each send.
+#[post("/api/admin/notifiers/test")]
+async fn test_notifiers(
+    data: web::Data<AppState>,
+    session: Session,
+    request: web::Json<NotifierTestRequest>,
+) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        match crate::notifiers::test_send(&data.config, request.name.as_deref()).await {
+            Ok(results) => HttpResponse::Ok().json(json!({"results": results})),
+            Err(e) => HttpResponse::NotFound().json(json!({"error": e})),
         }
     } else {
-        cached_json
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    metric: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    step: Option<i64>,
+}
+
+/// GET /api/history?metric=cpu_usage&from=&to=&step=
+///
+/// Returns recorded samples of a metric. `from` and `to` are unix timestamps
+/// (default: the last hour) and `step` merges points into buckets of that
+/// many seconds. The raw, 1-minute or 1-hour tier is chosen to fit the range
+/// and step. Without `metric`, lists the available metrics.
+#[get("/api/history")]
+async fn get_history(data: web::Data<AppState>, req: HttpRequest, session: Session, query: web::Query<HistoryQuery>) -> impl Responder {
+    if revoked(&data, &req) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Credential revoked"}));
+    }
+    if !peer_authorized(&data, &req) && !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
+    let history = data.history.lock().unwrap();
+    let metric = match &query.metric {
+        Some(metric) => metric,
+        None => return HttpResponse::Ok().json(json!({"metrics": history.series_names()})),
     };
-    
-    let response = json!({
-        "name": local_name,
-        "description": local_description,
-        "location": local_location,
-        "version": PYLON_VERSION,
-        "cached": filtered_cached,
-        "polled": sys_data.polled,
-        "remote_pylons": config.remote_pylons,
-    });
-    HttpResponse::Ok().json(response)
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = query.from.unwrap_or(to - 3600);
+    match history.query(metric, from, to, query.step) {
+        Some((tier, points)) => HttpResponse::Ok().json(json!({
+            "metric": metric,
+            "tier": tier.name(),
+            "from": from,
+            "to": to,
+            "step": query.step,
+            "points": points,
+        })),
+        None => HttpResponse::NotFound().json(json!({
+            "error": format!("Unknown metric '{}'", metric),
+            "metrics": history.series_names(),
+        })),
+    }
+}
+
+/// The key a remote is tracked under, given its pylon id or its "ip:port".
+fn resolve_remote_key(data: &AppState, key: String) -> String {
+    let statuses = data.remote_statuses.lock().unwrap();
+    if statuses.contains_key(&key) {
+        return key;
+    }
+    statuses.iter()
+        .find(|(_, status)| format!("{}:{}", status.ip, status.port) == key)
+        .map(|(tracked, _)| tracked.clone())
+        .unwrap_or(key)
+}
+
+/// GET /api/remotes/{key}/history?metric=cpu_usage&from=&to=&step=
+///
+/// Returns the recorded history of one remote pylon's metric, with the same
+/// parameters as /api/history. `key` is the remote's pylon id or "ip:port".
+#[get("/api/remotes/{key}/history")]
+async fn remote_history(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<String>,
+    query: web::Query<HistoryQuery>,
+) -> impl Responder {
+    if !peer_authorized(&data, &req) && !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
+    let key = resolve_remote_key(&data, path.into_inner());
+    let metric = match &query.metric {
+        Some(metric) => metric,
+        None => return HttpResponse::BadRequest().json(json!({"error": "Missing metric"})),
+    };
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = query.from.unwrap_or(to - 3600);
+    let history = data.history.lock().unwrap();
+    match history.query(&crate::remote::remote_series(&key, metric), from, to, query.step) {
+        Some((tier, points)) => HttpResponse::Ok().json(json!({
+            "remote": key,
+            "metric": metric,
+            "tier": tier.name(),
+            "from": from,
+            "to": to,
+            "step": query.step,
+            "points": points,
+        })),
+        None => HttpResponse::NotFound().json(json!({"error": format!("No history for '{}' on {}", metric, key)})),
+    }
+}
+
+#[derive(Deserialize)]
+struct WindowQuery {
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+/// GET /api/remotes/{key}/availability?from=&to=
+///
+/// Returns a remote pylon's online/offline transitions and uptime percentage
+/// over the window (default: the last 24 hours).
+#[get("/api/remotes/{key}/availability")]
+async fn remote_availability(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    session: Session,
+    path: web::Path<String>,
+    query: web::Query<WindowQuery>,
+) -> impl Responder {
+    if !peer_authorized(&data, &req) && !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
+    let key = resolve_remote_key(&data, path.into_inner());
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+    let from = query.from.unwrap_or(to - 86400);
+    match data.history.lock().unwrap().availability(&key, from, to) {
+        Some(availability) => HttpResponse::Ok().json(availability),
+        None => HttpResponse::NotFound().json(json!({"error": format!("No availability data for {}", key)})),
+    }
 }
 
 /// GET /api/remotes
 ///
 /// Returns remote pylon statuses as JSON.
 #[get("/api/remotes")]
-async fn remotes(data: web::Data<AppState>) -> impl Responder {
+async fn remotes(data: web::Data<AppState>, req: HttpRequest, session: Session) -> impl Responder {
+    if !peer_authorized(&data, &req) && !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
     let statuses = data.remote_statuses.lock().unwrap();
     let response: Vec<_> = statuses.values().cloned().collect();
     HttpResponse::Ok().json(response)
@@ -270,11 +686,14 @@ async fn remotes(data: web::Data<AppState>) -> impl Responder {
 
 /// GET /api/config/pylons
 ///
-/// Returns the current remote pylons configuration.
+/// Returns the current remote pylons configuration, without the tokens.
 #[get("/api/config/pylons")]
-async fn get_pylons(data: web::Data<AppState>) -> impl Responder {
+async fn get_pylons(data: web::Data<AppState>, session: Session) -> impl Responder {
+    if !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
     let config = data.config.read().unwrap();
-    HttpResponse::Ok().json(&config.remote_pylons)
+    HttpResponse::Ok().json(remotes_without_tokens(&config))
 }
 
 #[derive(Deserialize)]
@@ -289,8 +708,12 @@ struct RemovePylonRequest {
 #[post("/api/config/pylons/add")]
 async fn add_pylon(
     data: web::Data<AppState>,
+    session: Session,
     new_pylon: web::Json<crate::config_manager::RemotePylonConfig>,
 ) -> impl Responder {
+    if !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
     let mut config = data.config.write().unwrap();
     if config.remote_pylons.is_none() {
         config.remote_pylons = Some(vec![]);
@@ -310,8 +733,12 @@ async fn add_pylon(
 #[post("/api/config/pylons/remove")]
 async fn remove_pylon(
     data: web::Data<AppState>,
+    session: Session,
     info: web::Json<RemovePylonRequest>,
 ) -> impl Responder {
+    if !is_admin(&session) {
+        return HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}));
+    }
     let mut config = data.config.write().unwrap();
     if let Some(ref mut pylons) = config.remote_pylons {
         pylons.retain(|p| !(p.ip == info.ip && p.port == info.port));
@@ -322,7 +749,213 @@ async fn remove_pylon(
     }
 }
 
+/// GET /api/admin/discovery
+///
+/// Returns the discovery mode, the peers awaiting approval and the rejected peers.
+#[get("/api/admin/discovery")]
+async fn get_discovery(data: web::Data<AppState>, session: Session) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        let mode = crate::discovery::DiscoveryMode::from_config(&data.config.read().unwrap());
+        let discovery = data.discovery.lock().unwrap();
+        // Peers offered by pairing or adoption come with a credential; adverts do not.
+        let pending: Vec<Value> = discovery.pending.iter().map(|peer| {
+            let mut listed = json!(peer);
+            listed["remote"]["token"] = json!("");
+            listed["has_token"] = json!(!peer.remote.token.is_empty());
+            listed
+        }).collect();
+        HttpResponse::Ok().json(json!({
+            "mode": mode.name(),
+            "pending": pending,
+            "rejected": discovery.rejected,
+        }))
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+#[derive(Deserialize)]
+struct DiscoveryRequest {
+    key: String,
+    // Token to poll an approved peer with; adverts do not carry one.
+    token: Option<String>,
+}
+
+/// POST /api/admin/discovery/approve
+///
+/// Adds a pending peer to `remote_pylons`.
+#[post("/api/admin/discovery/approve")]
+async fn approve_peer(data: web::Data<AppState>, session: Session, request: web::Json<DiscoveryRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        match data.discovery.lock().unwrap().approve(&data.config, &request.key, request.token.clone()) {
+            Ok(()) => HttpResponse::Ok().json(json!({"status": "approved"})),
+            Err(e) => HttpResponse::BadRequest().json(json!({"error": e})),
+        }
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+/// POST /api/admin/discovery/reject
+///
+/// Drops a pending peer; it is not offered again until unrejected.
+#[post("/api/admin/discovery/reject")]
+async fn reject_peer(data: web::Data<AppState>, session: Session, request: web::Json<DiscoveryRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        data.discovery.lock().unwrap().reject(&request.key);
+        HttpResponse::Ok().json(json!({"status": "rejected"}))
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+/// POST /api/admin/discovery/unreject
+#[post("/api/admin/discovery/unreject")]
+async fn unreject_peer(data: web::Data<AppState>, session: Session, request: web::Json<DiscoveryRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        data.discovery.lock().unwrap().unreject(&request.key);
+        HttpResponse::Ok().json(json!({"status": "unrejected"}))
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+/// GET /api/admin/lan
+///
+/// Lists the pylons heard on the local network that are not configured yet.
+#[get("/api/admin/lan")]
+async fn get_lan(data: web::Data<AppState>, session: Session) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        let config = data.config.read().unwrap();
+        HttpResponse::Ok().json(crate::lan::status(&config, &data.lan))
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+#[derive(Deserialize)]
+struct AdoptPylonRequest {
+    id: String,
+    token: Option<String>,
+}
+
+/// POST /api/admin/lan/adopt
+///
+/// Adds a pylon heard on the local network to `remote_pylons`, exchanging
+/// tokens with it unless `token` is given.
+#[post("/api/admin/lan/adopt")]
+async fn adopt_lan_pylon(data: web::Data<AppState>, session: Session, request: web::Json<AdoptPylonRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        let request = request.into_inner();
+        let token = request.token.filter(|token| !token.is_empty());
+        match crate::lan::adopt(&data.config, &data.pairing, &data.lan, &request.id, token, data.port).await {
+            Ok(remote) => HttpResponse::Ok().json(json!({"status": "adopted", "pylon": remote})),
+            Err(e) => HttpResponse::BadRequest().json(json!({"error": e})),
+        }
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+/// POST /api/lan/adopt
+///
+/// Called by a pylon adopting this one; requires the shared `lan_secret`.
+#[post("/api/lan/adopt")]
+async fn answer_adoption(data: web::Data<AppState>, req: HttpRequest, request: web::Json<crate::lan::AdoptRequest>) -> impl Responder {
+    let Some(peer) = req.peer_addr() else {
+        return HttpResponse::BadRequest().json(json!({"error": "unknown peer address"}));
+    };
+    match crate::lan::answer_adoption(&data.config, &data.pairing, &data.discovery, request.into_inner(), peer.ip().to_string()) {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::Forbidden().json(json!({"error": e})),
+    }
+}
+
+/// GET /api/admin/pairing
+///
+/// Returns the outstanding pairing codes and the credentials issued to
+/// paired pylons (without the tokens).
+#[get("/api/admin/pairing")]
+async fn get_pairing(data: web::Data<AppState>, session: Session) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        let mut pairing = data.pairing.lock().unwrap();
+        let peers: Vec<Value> = pairing.credentials.iter()
+            .map(|c| json!({"id": c.id, "name": c.name, "address": c.address, "created_at": c.created_at, "revoked_at": c.revoked_at}))
+            .collect();
+        HttpResponse::Ok().json(json!({"codes": pairing.active_codes(), "peers": peers}))
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+/// POST /api/admin/pairing/code
+///
+/// Generates a pairing code valid for 10 minutes.
+#[post("/api/admin/pairing/code")]
+async fn pairing_code(data: web::Data<AppState>, session: Session) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        HttpResponse::Ok().json(data.pairing.lock().unwrap().generate_code())
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+#[derive(Deserialize)]
+struct JoinRequest {
+    address: String,
+    code: String,
+}
+
+/// POST /api/admin/pairing/join
+///
+/// Pairs with the pylon at `address` using a code generated there.
+#[post("/api/admin/pairing/join")]
+async fn pairing_join(data: web::Data<AppState>, session: Session, request: web::Json<JoinRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        match crate::pairing::join(&data.config, &data.pairing, request.address.trim(), &request.code, data.port).await {
+            Ok(remote) => HttpResponse::Ok().json(json!({"status": "paired", "pylon": remote})),
+            Err(e) => HttpResponse::BadRequest().json(json!({"error": e})),
+        }
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+#[derive(Deserialize)]
+struct RevokeRequest {
+    id: String,
+}
+
+/// POST /api/admin/pairing/revoke
+#[post("/api/admin/pairing/revoke")]
+async fn pairing_revoke(data: web::Data<AppState>, session: Session, request: web::Json<RevokeRequest>) -> impl Responder {
+    if let Ok(Some(true)) = session.get::<bool>("admin_authenticated") {
+        if data.pairing.lock().unwrap().revoke(&request.id) {
+            HttpResponse::Ok().json(json!({"status": "revoked"}))
+        } else {
+            HttpResponse::NotFound().json(json!({"error": "No such credential"}))
+        }
+    } else {
+        HttpResponse::Unauthorized().json(json!({"error": "Unauthorized"}))
+    }
+}
+
+/// POST /api/pairing/complete
+///
+/// Called by a pylon joining with one of our pairing codes.
+#[post("/api/pairing/complete")]
+async fn pairing_complete(data: web::Data<AppState>, req: HttpRequest, request: web::Json<crate::pairing::PairRequest>) -> impl Responder {
+    let Some(peer) = req.peer_addr() else {
+        return HttpResponse::BadRequest().json(json!({"error": "unknown peer address"}));
+    };
+    match crate::pairing::complete(&data.config, &data.pairing, &data.discovery, request.into_inner(), peer.ip().to_string()) {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => HttpResponse::Forbidden().json(json!({"error": e})),
+    }
+}
+
 /// Checks if a port is available.
+#[allow(clippy::redundant_pattern_matching)]
 async fn port_available(port: u16) -> bool {
     use tokio::net::TcpListener;
     match TcpListener::bind(("127.0.0.1", port)).await {
@@ -343,31 +976,150 @@ pub async fn find_open_port(start_port: u16) -> u16 {
     port
 }
 
+/// The admin session cookie. Pylon serves plain HTTP, so the cookie cannot
+/// be marked Secure (browsers would drop it).
+fn session_middleware(key: Key) -> SessionMiddleware<CookieSessionStore> {
+    SessionMiddleware::builder(CookieSessionStore::default(), key)
+        .cookie_secure(false)
+        .build()
+}
+
 /// Runs the web server.
 pub async fn run_server(port: u16, state: AppState) -> std::io::Result<()> {
     println!("Starting server on http://127.0.0.1:{}", port);
+    // A fresh key on every start, so session cookies cannot be forged; admins log in again after a restart.
+    let key = Key::generate();
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(state.clone()))
             .wrap(Logger::default())
-            .wrap(SessionMiddleware::new(
-                CookieSessionStore::default(),
-                Key::from(&[0; 64]) // In production, use a secure key!
-            ))
+            .wrap(session_middleware(key.clone()))
             // Route to serve embedded static files.
             .route("/static/{filename:.*}", web::get().to(serve_embedded_file))
             .service(index)
             .service(login)
             .service(metrics)
+            .service(ingest)
+            .service(federate)
             .service(remotes)
+            .service(get_history)
+            .service(get_alerts)
+            .service(remote_history)
+            .service(remote_availability)
             .service(get_pylons)
             .service(add_pylon)
             .service(remove_pylon)
             .service(admin_content)
+            .service(admin_logs)
+            .service(test_notifiers)
+            .service(ack_alert)
+            .service(add_silence)
+            .service(remove_silence)
+            .service(get_discovery)
+            .service(approve_peer)
+            .service(reject_peer)
+            .service(unreject_peer)
+            .service(get_lan)
+            .service(adopt_lan_pylon)
+            .service(answer_adoption)
+            .service(get_pairing)
+            .service(pairing_code)
+            .service(pairing_join)
+            .service(pairing_revoke)
+            .service(pairing_complete)
 			.service(check_update_endpoint)
     })
     .bind(("0.0.0.0", port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn state() -> AppState {
+        let remote: crate::config_manager::RemotePylonConfig =
+            serde_json::from_value(json!({"ip": "10.0.0.9", "port": 6989, "token": "remote-secret"})).unwrap();
+        let config = crate::config_manager::Config {
+            token: "admin-key".into(),
+            remote_pylons: Some(vec![remote]),
+            ..Default::default()
+        };
+        let history = crate::history::HistoryStore::open(&crate::config_manager::HistoryConfig { enabled: Some(false), ..Default::default() });
+        AppState {
+            config: Arc::new(RwLock::new(config)),
+            system_data: Arc::new(Mutex::new(SystemData::new())),
+            remote_statuses: Default::default(),
+            history: Arc::new(Mutex::new(history)),
+            alerts: Default::default(),
+            ingest: tokio::sync::mpsc::unbounded_channel().0,
+            discovery: Default::default(),
+            lan: Default::default(),
+            pairing: Default::default(),
+            federation: Default::default(),
+            port: 6989,
+        }
+    }
+
+    #[actix_web::test]
+    async fn reads_need_a_credential_or_an_admin_session() {
+        let state = state();
+        let revoked = state.pairing.lock().unwrap().issue(None, "10.0.0.3:6989".into(), None);
+        let paired = state.pairing.lock().unwrap().issue(None, "10.0.0.4:6989".into(), None);
+        state.pairing.lock().unwrap().revoke(&revoked.id);
+        let app = actix_web::test::init_service(App::new()
+            .app_data(web::Data::new(state))
+            .wrap(session_middleware(Key::generate()))
+            .service(login)
+            .service(metrics)
+            .service(remotes)
+            .service(get_alerts)
+            .service(remote_availability)
+            .service(get_pylons)
+            .service(add_pylon)).await;
+        let status = |request: TestRequest| {
+            let app = &app;
+            async move { actix_web::test::call_service(app, request.to_request()).await.status().as_u16() }
+        };
+
+        for uri in ["/api/metrics", "/api/remotes", "/api/alerts", "/api/remotes/x/availability", "/api/config/pylons"] {
+            assert_eq!(status(TestRequest::get().uri(uri)).await, 401, "{}", uri);
+            // Browser headers prove nothing.
+            assert_eq!(status(TestRequest::get().uri(uri).insert_header(("Sec-Fetch-Site", "same-origin"))).await, 401, "{}", uri);
+            let with_revoked = TestRequest::get().uri(uri).insert_header(("Authorization", format!("Bearer {}", revoked.token)));
+            assert_eq!(status(with_revoked).await, 401, "{}", uri);
+        }
+        let bearer = |token: &str| ("Authorization", format!("Bearer {}", token));
+        assert_eq!(status(TestRequest::get().uri("/api/metrics").insert_header(bearer(&paired.token))).await, 200);
+        assert_eq!(status(TestRequest::get().uri("/api/alerts").insert_header(bearer("admin-key"))).await, 200);
+        // The pylon list is for the admin page only.
+        assert_eq!(status(TestRequest::get().uri("/api/config/pylons").insert_header(bearer(&paired.token))).await, 401);
+        assert_eq!(status(TestRequest::post().uri("/api/config/pylons/add").set_json(json!({"ip": "10.0.0.5", "port": 1, "token": ""}))).await, 401);
+        assert_eq!(status(TestRequest::post().uri("/api/login").set_json(json!({"token": ""}))).await, 401);
+
+        let logged_in = actix_web::test::call_service(&app, TestRequest::post().uri("/api/login")
+            .set_json(json!({"token": "admin-key"})).to_request()).await;
+        let cookie = logged_in.response().cookies().next().unwrap().into_owned();
+        for uri in ["/api/metrics", "/api/remotes", "/api/alerts"] {
+            assert_eq!(status(TestRequest::get().uri(uri).cookie(cookie.clone())).await, 200, "{}", uri);
+        }
+        let pylons = actix_web::test::call_service(&app, TestRequest::get().uri("/api/config/pylons").cookie(cookie).to_request()).await;
+        let body = String::from_utf8(actix_web::test::read_body(pylons).await.to_vec()).unwrap();
+        assert!(body.contains("10.0.0.9"));
+        assert!(!body.contains("remote-secret"));
+    }
+
+    #[test]
+    fn local_metrics_do_not_advertise_tokens() {
+        let remote: crate::config_manager::RemotePylonConfig =
+            serde_json::from_value(json!({"ip": "10.0.0.9", "port": 6989, "token": "secret"})).unwrap();
+        let config = crate::config_manager::Config { remote_pylons: Some(vec![remote]), ..Default::default() };
+        let served = local_metrics(&SystemData::new(), &config);
+        assert_eq!(served["remote_pylons"][0]["ip"], "10.0.0.9");
+        assert_eq!(served["remote_pylons"][0]["token"], "");
+        assert!(!served.to_string().contains("secret"));
+    }
+}
@@ -0,0 +1,198 @@
+// src/sockets.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex, RwLock};
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+use crate::config_manager::Config;
+use crate::system_info::SystemData;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ListeningSocket {
+    // "tcp", "tcp6", "udp" or "udp6".
+    pub protocol: String,
+    pub address: String,
+    pub port: u16,
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SocketInventory {
+    pub listening: Vec<ListeningSocket>,
+    // Number of TCP (v4 and v6) sockets in each state, e.g. "ESTABLISHED" => 12.
+    pub tcp_states: BTreeMap<String, usize>,
+}
+
+/// Names for the `st` column of /proc/net/tcp (see include/net/tcp_states.h).
+fn tcp_state_name(state: u8) -> &'static str {
+    match state {
+        0x01 => "ESTABLISHED",
+        0x02 => "SYN_SENT",
+        0x03 => "SYN_RECV",
+        0x04 => "FIN_WAIT1",
+        0x05 => "FIN_WAIT2",
+        0x06 => "TIME_WAIT",
+        0x07 => "CLOSE",
+        0x08 => "CLOSE_WAIT",
+        0x09 => "LAST_ACK",
+        0x0A => "LISTEN",
+        0x0B => "CLOSING",
+        0x0C => "NEW_SYN_RECV",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Parses an "ADDR:PORT" column. Addresses are hex words in host byte order.
+fn parse_address(field: &str) -> Option<(String, u16)> {
+    let (addr_hex, port_hex) = field.split_once(':')?;
+    let port = u16::from_str_radix(port_hex, 16).ok()?;
+    let address = match addr_hex.len() {
+        8 => Ipv4Addr::from(u32::from_str_radix(addr_hex, 16).ok()?.to_le_bytes()).to_string(),
+        32 => {
+            let mut bytes = [0u8; 16];
+            for (i, chunk) in bytes.chunks_mut(4).enumerate() {
+                let word = u32::from_str_radix(&addr_hex[i * 8..i * 8 + 8], 16).ok()?;
+                chunk.copy_from_slice(&word.to_le_bytes());
+            }
+            Ipv6Addr::from(bytes).to_string()
+        },
+        _ => return None,
+    };
+    Some((address, port))
+}
+
+/// Maps socket inodes to the process holding them by walking /proc/*/fd.
+fn socket_owners() -> HashMap<u64, (u32, String)> {
+    let mut owners = HashMap::new();
+    let Ok(procs) = std::fs::read_dir("/proc") else { return owners };
+    for entry in procs.flatten() {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else { continue };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else { continue };
+        let name = std::fs::read_to_string(entry.path().join("comm"))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default();
+        for fd in fds.flatten() {
+            if let Ok(target) = std::fs::read_link(fd.path()) {
+                let target = target.to_string_lossy();
+                if let Some(inode) = target.strip_prefix("socket:[").and_then(|s| s.strip_suffix(']')) {
+                    if let Ok(inode) = inode.parse::<u64>() {
+                        owners.entry(inode).or_insert_with(|| (pid, name.clone()));
+                    }
+                }
+            }
+        }
+    }
+    owners
+}
+
+/// Reads /proc/net/{tcp,tcp6,udp,udp6}.
+fn collect_sockets() -> SocketInventory {
+    let mut inventory = SocketInventory::default();
+    let mut listening_inodes = Vec::new();
+
+    for protocol in ["tcp", "tcp6", "udp", "udp6"] {
+        let Ok(content) = std::fs::read_to_string(format!("/proc/net/{}", protocol)) else { continue };
+        for line in content.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                continue;
+            }
+            let Ok(state) = u8::from_str_radix(fields[3], 16) else { continue };
+            let is_tcp = protocol.starts_with("tcp");
+            if is_tcp {
+                *inventory.tcp_states.entry(tcp_state_name(state).to_string()).or_insert(0) += 1;
+            }
+            // TCP listeners are in LISTEN; bound UDP sockets show up as CLOSE (unconnected).
+            let listening = if is_tcp { state == 0x0A } else { state == 0x07 };
+            if !listening {
+                continue;
+            }
+            if let Some((address, port)) = parse_address(fields[1]) {
+                let inode = fields[9].parse::<u64>().unwrap_or(0);
+                listening_inodes.push(inode);
+                inventory.listening.push(ListeningSocket {
+                    protocol: protocol.to_string(),
+                    address,
+                    port,
+                    pid: None,
+                    process: None,
+                });
+            }
+        }
+    }
+
+    let owners = socket_owners();
+    for (socket, inode) in inventory.listening.iter_mut().zip(listening_inodes) {
+        if let Some((pid, name)) = owners.get(&inode) {
+            socket.pid = Some(*pid);
+            socket.process = Some(name.clone());
+        }
+    }
+    inventory.listening.sort_by(|a, b| (a.port, &a.protocol).cmp(&(b.port, &b.protocol)));
+    inventory
+}
+
+/// Periodically refreshes the listening socket and TCP state inventory.
+pub async fn poll_sockets(
+    data: Arc<Mutex<SystemData>>,
+    config_arc: Arc<RwLock<Config>>,
+    mut shutdown: watch::Receiver<bool>
+) {
+    loop {
+        let interval = config_arc.read().unwrap().socket_check_secs.unwrap_or(10).max(1);
+        if let Ok(inventory) = tokio::task::spawn_blocking(collect_sockets).await {
+            data.lock().unwrap().sockets = inventory;
+        }
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down socket inventory poller.");
+                    break;
+                }
+            },
+            _ = sleep(Duration::from_secs(interval)) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_little_endian_ipv4() {
+        assert_eq!(parse_address("0100007F:1F90"), Some(("127.0.0.1".into(), 8080)));
+        assert_eq!(parse_address("00000000:0016"), Some(("0.0.0.0".into(), 22)));
+        assert_eq!(parse_address("0A01A8C0:01BB"), Some(("192.168.1.10".into(), 443)));
+    }
+
+    #[test]
+    fn parses_ipv6_words() {
+        assert_eq!(parse_address("00000000000000000000000001000000:0050"), Some(("::1".into(), 80)));
+        assert_eq!(parse_address("00000000000000000000000000000000:1B59"), Some(("::".into(), 7001)));
+        assert_eq!(parse_address("0000000000000000FFFF00000100007F:0035"), Some(("::ffff:127.0.0.1".into(), 53)));
+        assert_eq!(parse_address("B80D0120000000000000000001000000:0050"), Some(("2001:db8::1".into(), 80)));
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        assert_eq!(parse_address(""), None);
+        assert_eq!(parse_address("0100007F"), None);
+        assert_eq!(parse_address("0100007F:"), None);
+        assert_eq!(parse_address("0100007F:XYZ"), None);
+        assert_eq!(parse_address("100007F:0050"), None);
+        assert_eq!(parse_address("G100007F:0050"), None);
+        assert_eq!(parse_address("0100007F:10000"), None);
+    }
+
+    #[test]
+    fn names_tcp_states() {
+        assert_eq!(tcp_state_name(0x01), "ESTABLISHED");
+        assert_eq!(tcp_state_name(0x0A), "LISTEN");
+        assert_eq!(tcp_state_name(0xFF), "UNKNOWN");
+    }
+}
@@ -3,17 +3,23 @@
 use sysinfo::{System, SystemExt, CpuExt, DiskExt, NetworkExt, LoadAvg, ProcessExt, PidExt};
 use tokio::time::{sleep, Duration};
 use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use chrono::{DateTime, Utc};
 use std::sync::{Mutex, Arc, RwLock};
+use regex::Regex;
+use crate::config_manager::SoftwareProbe;
+use crate::flapping::StateTracker;
+
+// The software whose versions `cached` carried as fixed keys before `[software]`.
+const LEGACY_SOFTWARE: &[&str] = &["apache", "php", "mariadb", "rust", "node", "npm"];
 
 #[derive(Debug, Serialize, Clone)]
 pub struct CachedInfo {
     pub os_version: String,
-    pub apache_version: String,
-    pub php_version: String,
-    pub mariadb_version: String,
-    pub rust_version: String,
-    pub node_version: String,
-    pub npm_version: String,
+    // Versions reported by the configured `[software]` probes, keyed by name.
+    pub software: HashMap<String, String>,
     pub processor: String,
     pub total_ram: u64,
     pub disk_capacity: u64,
@@ -26,7 +32,10 @@ pub struct CachedInfo {
 #[derive(Debug, Serialize, Clone)]
 pub struct ServiceStatus {
     pub name: String,
+    // Debounced, see flapping.rs.
     pub running: bool,
+    pub transitions: u64,
+    pub flapping: bool,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -36,6 +45,17 @@ pub struct ProcessInfo {
     pub memory: u64,
 }
 
+/// Space and inode usage of one mounted filesystem.
+#[derive(Debug, Serialize, Clone)]
+pub struct MountUsage {
+    pub mount_point: String,
+    pub file_system: String,
+    pub total: u64,
+    pub available: u64,
+    pub inodes_total: u64,
+    pub inodes_free: u64,
+}
+
 #[derive(Debug, Serialize, Clone, Default)]
 pub struct PolledMetrics {
     pub cpu_usage: f32,
@@ -49,17 +69,107 @@ pub struct PolledMetrics {
     pub swap_total: u64,
     pub disk_free: u64,
     pub disk_usage_percent: f32,
+    pub mounts: Vec<MountUsage>,
     pub services: Vec<ServiceStatus>,
     pub top_processes: Vec<ProcessInfo>,
 }
 
+/// Pending OS package updates, refreshed on a slow cadence.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PackageStatus {
+    // "apt", "dnf" or "unknown" when no supported package manager was found.
+    pub manager: String,
+    pub upgradable: usize,
+    pub security_updates: usize,
+    pub reboot_required: bool,
+    // Packages listed in /var/run/reboot-required.pkgs.
+    pub reboot_packages: Vec<String>,
+    // When the package index was last refreshed (apt update / dnf makecache).
+    pub index_refreshed: Option<DateTime<Utc>>,
+    pub checked_at: Option<DateTime<Utc>>,
+}
+
+impl PolledMetrics {
+    /// The numeric fields recorded into the history store, by series name.
+    /// Each mount adds `disk_used:<mount>` and `inodes_used:<mount>`.
+    pub fn history_values(&self) -> Vec<(String, f64)> {
+        let mut values = vec![
+            ("cpu_usage".into(), self.cpu_usage as f64),
+            ("used_ram".into(), self.used_ram as f64),
+            ("available_ram".into(), self.available_ram as f64),
+            ("network_received".into(), self.network_received as f64),
+            ("network_transmitted".into(), self.network_transmitted as f64),
+            ("load_average_one".into(), self.load_average.one),
+            ("load_average_five".into(), self.load_average.five),
+            ("load_average_fifteen".into(), self.load_average.fifteen),
+            ("swap_used".into(), self.swap_used as f64),
+            ("swap_total".into(), self.swap_total as f64),
+            ("disk_free".into(), self.disk_free as f64),
+            ("disk_usage_percent".into(), self.disk_usage_percent as f64),
+        ];
+        for mount in &self.mounts {
+            values.push((format!("disk_used:{}", mount.mount_point), mount.total.saturating_sub(mount.available) as f64));
+            if mount.inodes_total > 0 {
+                values.push((format!("inodes_used:{}", mount.mount_point), mount.inodes_total.saturating_sub(mount.inodes_free) as f64));
+            }
+        }
+        values
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SystemData {
     pub cached: CachedInfo,
     pub polled: PolledMetrics,
+    pub packages: PackageStatus,
+    // Filled in by `certs::poll_certificates`.
+    pub certificates: Vec<crate::certs::CertificateInfo>,
+    // Filled in by `log_watcher::watch_logs`.
+    pub logs: Vec<crate::log_watcher::LogFileStatus>,
+    // Filled in by `sockets::poll_sockets`.
+    pub sockets: crate::sockets::SocketInventory,
+    // Filled in by `forecast::poll_forecasts`.
+    pub forecasts: Vec<crate::forecast::Forecast>,
 }
 
 impl SystemData {
+    /// The local metrics as served by /api/metrics (without the identity fields)
+    /// and evaluated by alert rules.
+    pub fn to_json(&self) -> serde_json::Value {
+        // Only the log counters are public; matching lines are served by /api/admin/logs.
+        let log_counters: Vec<_> = self.logs.iter().map(|log| serde_json::json!({
+            "path": log.path,
+            "counters": log.counters,
+            "rotations": log.rotations,
+            "error": log.error,
+        })).collect();
+
+        serde_json::json!({
+            "cached": self.cached_json(),
+            "polled": self.polled,
+            "packages": self.packages,
+            "certificates": self.certificates,
+            "logs": log_counters,
+            "sockets": self.sockets,
+            "forecasts": self.forecasts,
+        })
+    }
+
+    /// `cached` with the fixed `<name>_version` keys older dashboards and peers
+    /// read, filled from the default probes (or "Not installed" when not configured).
+    fn cached_json(&self) -> serde_json::Value {
+        let mut cached = serde_json::to_value(&self.cached).unwrap_or_default();
+        if let Some(object) = cached.as_object_mut() {
+            for name in LEGACY_SOFTWARE {
+                let version = self.cached.software.get(*name)
+                    .cloned()
+                    .unwrap_or_else(|| "Not installed".to_string());
+                object.insert(format!("{}_version", name), serde_json::Value::String(version));
+            }
+        }
+        cached
+    }
+
     pub fn new() -> Self {
         let mut sys = System::new_all();
         sys.refresh_all();
@@ -80,12 +190,8 @@ impl SystemData {
 
         let cached = CachedInfo {
             os_version: get_os_version(),
-            apache_version: get_command_version("apache2", "-v"),
-            php_version: get_command_version("php", "-v"),
-            mariadb_version: get_command_version("mysql", "--version"),
-            rust_version: get_command_version("rustc", "--version"),
-            node_version: get_command_version("node", "-v"),
-            npm_version: get_command_version("npm", "-v"),
+            // Filled in by `poll_software_versions` once it has run the probes.
+            software: HashMap::new(),
             processor: get_processor_info(),
             total_ram: sys.total_memory(),
             disk_capacity: total_capacity,
@@ -98,6 +204,11 @@ impl SystemData {
         Self {
             cached,
             polled: PolledMetrics::default(),
+            packages: PackageStatus::default(),
+            certificates: Vec::new(),
+            logs: Vec::new(),
+            sockets: Default::default(),
+            forecasts: Vec::new(),
         }
     }
 }
@@ -113,14 +224,29 @@ fn get_os_version() -> String {
     "Unknown OS".to_string()
 }
 
-fn get_command_version(cmd: &str, arg: &str) -> String {
-    if let Ok(output) = std::process::Command::new(cmd).arg(arg).output() {
-        if output.status.success() {
-            let s = String::from_utf8_lossy(&output.stdout).to_string();
-            return s.lines().next().unwrap_or("Unknown").to_string();
-        }
+/// Runs a software probe and extracts its version string.
+/// Some tools (e.g. `java -version`) print to stderr, so that is used when stdout is empty.
+fn get_command_version(probe: &SoftwareProbe) -> String {
+    let args = probe.args.clone().unwrap_or_default();
+    let output = match Command::new(&probe.command).args(&args).output() {
+        Ok(output) if output.status.success() => output,
+        _ => return "Not installed".to_string(),
+    };
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    if text.trim().is_empty() {
+        text = String::from_utf8_lossy(&output.stderr).to_string();
+    }
+
+    match &probe.regex {
+        Some(pattern) => match Regex::new(pattern) {
+            Ok(re) => re.captures(&text)
+                .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
+                .map(|m| m.as_str().trim().to_string())
+                .unwrap_or_else(|| "Unknown".to_string()),
+            Err(e) => format!("Invalid regex: {}", e),
+        },
+        None => text.lines().next().unwrap_or("Unknown").to_string(),
     }
-    "Not installed".to_string()
 }
 
 fn get_processor_info() -> String {
@@ -138,13 +264,17 @@ fn get_processor_info() -> String {
 }
 
 /// Poll system metrics (including the top five memory‐hungry processes)
-/// Now also takes a config_arc to use its `services` list.
+/// Now also takes a config_arc to use its `services` list, and records
+/// every sample into the history store.
+#[allow(clippy::unnecessary_sort_by)]
 pub async fn poll_system_metrics(
     data: Arc<Mutex<SystemData>>,
     config_arc: Arc<RwLock<crate::config_manager::Config>>,
+    history: crate::history::SharedHistory,
     mut shutdown: tokio::sync::watch::Receiver<bool>
 ) {
     let mut sys = System::new_all();
+    let mut trackers: HashMap<String, StateTracker> = HashMap::new();
     loop {
         if *shutdown.borrow() {
             println!("Shutting down system metrics poller.");
@@ -164,6 +294,23 @@ pub async fn poll_system_metrics(
             used_disk as f32 / total_capacity as f32
         } else { 0.0 };
 
+        let mut mounts: Vec<MountUsage> = Vec::new();
+        for disk in sys.disks() {
+            let mount_point = disk.mount_point().to_string_lossy().to_string();
+            if mounts.iter().any(|m| m.mount_point == mount_point) {
+                continue;
+            }
+            let (inodes_total, inodes_free) = inode_counts(disk.mount_point()).unwrap_or((0, 0));
+            mounts.push(MountUsage {
+                mount_point,
+                file_system: String::from_utf8_lossy(disk.file_system()).to_string(),
+                total: disk.total_space(),
+                available: disk.available_space(),
+                inodes_total,
+                inodes_free,
+            });
+        }
+
         let (mut network_received, mut network_transmitted) = (0, 0);
         for (_iface, data_net) in sys.networks() {
             network_received += data_net.received();
@@ -179,8 +326,9 @@ pub async fn poll_system_metrics(
         let services: Vec<ServiceStatus> = {
             let config = config_arc.read().unwrap();
             let services_to_monitor = config.services.clone().unwrap_or_else(|| vec!["apache2".into(), "mariadb".into()]);
+            let hysteresis = config.hysteresis.clone().unwrap_or_default();
             let mut statuses: Vec<ServiceStatus> = services_to_monitor.into_iter()
-                .map(|s| ServiceStatus { name: s.clone(), running: false })
+                .map(|s| ServiceStatus { name: s.clone(), running: false, transitions: 0, flapping: false })
                 .collect();
             for process in sys.processes().values() {
                 let proc_name = process.name().to_lowercase();
@@ -190,6 +338,15 @@ pub async fn poll_system_metrics(
                     }
                 }
             }
+            // A single sample without the process does not take the service down.
+            let now = Utc::now().timestamp();
+            trackers.retain(|name, _| statuses.iter().any(|s| &s.name == name));
+            for status in statuses.iter_mut() {
+                let tracker = trackers.entry(status.name.clone()).or_default();
+                status.running = tracker.observe(status.running, now, &hysteresis);
+                status.transitions = tracker.transitions();
+                status.flapping = tracker.flapping();
+            }
             statuses
         };
 
@@ -211,7 +368,7 @@ pub async fn poll_system_metrics(
             (0, 0)
         };
 
-        {
+        let history_values = {
             let mut data_lock = data.lock().unwrap();
             data_lock.polled.cpu_usage = cpu_usage;
             data_lock.polled.used_ram = used_ram;
@@ -224,11 +381,15 @@ pub async fn poll_system_metrics(
             data_lock.polled.swap_used = swap_used;
             data_lock.polled.disk_free = total_free;
             data_lock.polled.disk_usage_percent = disk_usage_percent;
+            data_lock.polled.mounts = mounts;
             data_lock.polled.services = services;
             data_lock.polled.top_processes = top_processes;
             data_lock.cached.average_cpu_speed_mhz = avg_cpu_speed;
             data_lock.cached.max_cpu_speed_mhz = max_cpu_speed;
-        }
+            data_lock.polled.history_values()
+        };
+        // Not under the SystemData lock: recording writes to the history files.
+        history.lock().unwrap().record(Utc::now().timestamp(), history_values);
 
         tokio::select! {
             _ = shutdown.changed() => {
@@ -241,3 +402,153 @@ pub async fn poll_system_metrics(
         }
     }
 }
+
+/// Total and free inodes of the filesystem mounted at `mount_point`.
+fn inode_counts(mount_point: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(mount_point.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some((stat.f_files as u64, stat.f_ffree as u64))
+}
+
+/// Periodically re-runs the configured `[software]` probes.
+/// The probes spawn external commands, so they run on the blocking thread pool.
+pub async fn poll_software_versions(
+    data: Arc<Mutex<SystemData>>,
+    config_arc: Arc<RwLock<crate::config_manager::Config>>,
+    mut shutdown: tokio::sync::watch::Receiver<bool>
+) {
+    loop {
+        let (probes, refresh_secs) = {
+            let config = config_arc.read().unwrap();
+            (
+                config.software.clone().unwrap_or_else(crate::config_manager::default_software_probes),
+                config.software_refresh_secs.unwrap_or(3600).max(1),
+            )
+        };
+
+        let versions = tokio::task::spawn_blocking(move || {
+            probes.iter()
+                .map(|(name, probe)| (name.clone(), get_command_version(probe)))
+                .collect::<HashMap<String, String>>()
+        }).await.unwrap_or_default();
+        data.lock().unwrap().cached.software = versions;
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down software version poller.");
+                    break;
+                }
+            },
+            _ = sleep(Duration::from_secs(refresh_secs)) => {}
+        }
+    }
+}
+
+fn command_exists(cmd: &str) -> bool {
+    Command::new("sh").arg("-c").arg(format!("command -v {}", cmd)).output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+fn file_modified(path: &str) -> Option<DateTime<Utc>> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from)
+}
+
+/// Counts pending updates using a simulated `apt-get upgrade`.
+/// Each "Inst" line is one package; those coming from a *-security suite are security updates.
+fn apt_updates() -> (usize, usize) {
+    let output = match Command::new("apt-get").args(["--simulate", "--quiet", "upgrade"]).output() {
+        Ok(output) => output,
+        Err(_) => return (0, 0),
+    };
+    let text = String::from_utf8_lossy(&output.stdout);
+    let installs: Vec<&str> = text.lines().filter(|line| line.starts_with("Inst ")).collect();
+    let security = installs.iter().filter(|line| line.contains("-security")).count();
+    (installs.len(), security)
+}
+
+/// Counts pending updates with `dnf check-update`, which exits with 100 when updates exist.
+fn dnf_updates() -> (usize, usize) {
+    let count_lines = |args: &[&str]| -> usize {
+        match Command::new("dnf").args(args).output() {
+            Ok(output) => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .take_while(|line| !line.starts_with("Obsoleting"))
+                .filter(|line| line.split_whitespace().count() == 3)
+                .count(),
+            Err(_) => 0,
+        }
+    };
+    (
+        count_lines(&["--quiet", "--cacheonly", "check-update"]),
+        count_lines(&["--quiet", "--cacheonly", "updateinfo", "list", "--security"]),
+    )
+}
+
+/// Gathers the current package update and reboot status.
+fn get_package_status() -> PackageStatus {
+    let mut status = PackageStatus {
+        checked_at: Some(Utc::now()),
+        ..Default::default()
+    };
+
+    if command_exists("apt-get") {
+        status.manager = "apt".into();
+        (status.upgradable, status.security_updates) = apt_updates();
+        status.index_refreshed = file_modified("/var/lib/apt/periodic/update-success-stamp")
+            .or_else(|| file_modified("/var/lib/apt/lists"));
+    } else if command_exists("dnf") {
+        status.manager = "dnf".into();
+        (status.upgradable, status.security_updates) = dnf_updates();
+        status.index_refreshed = file_modified("/var/cache/dnf");
+        // needs-restarting (dnf-utils) exits with 1 when a reboot is needed.
+        if let Ok(output) = Command::new("needs-restarting").arg("-r").output() {
+            status.reboot_required = output.status.code() == Some(1);
+        }
+    } else {
+        status.manager = "unknown".into();
+    }
+
+    if Path::new("/var/run/reboot-required").exists() {
+        status.reboot_required = true;
+        if let Ok(content) = std::fs::read_to_string("/var/run/reboot-required.pkgs") {
+            status.reboot_packages = content.lines()
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty())
+                .collect();
+            status.reboot_packages.sort();
+            status.reboot_packages.dedup();
+        }
+    }
+    status
+}
+
+/// Periodically checks for pending OS updates and whether a reboot is required.
+/// Package managers are slow, so this runs far less often than the metrics poller.
+pub async fn poll_package_status(
+    data: Arc<Mutex<SystemData>>,
+    config_arc: Arc<RwLock<crate::config_manager::Config>>,
+    mut shutdown: tokio::sync::watch::Receiver<bool>
+) {
+    loop {
+        let interval = config_arc.read().unwrap().package_check_secs.unwrap_or(3600).max(60);
+        if let Ok(status) = tokio::task::spawn_blocking(get_package_status).await {
+            data.lock().unwrap().packages = status;
+        }
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down package status poller.");
+                    break;
+                }
+            },
+            _ = sleep(Duration::from_secs(interval)) => {}
+        }
+    }
+}
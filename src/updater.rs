@@ -21,6 +21,7 @@ fn disable_auto_update(config_arc: &Arc<RwLock<Config>>) {
 /// Checks for a new version and, if found, downloads and installs it.
 /// This updater backs up the current binary and then replaces it.
 /// Finally, it uses `sudo systemctl restart pylon` to restart the service.
+#[allow(clippy::len_zero)]
 pub async fn check_for_update(config_arc: Arc<RwLock<Config>>) -> Result<bool, Box<dyn std::error::Error>> {
     println!("================== Starting Update Check ==================");
     let client = Client::new();
@@ -0,0 +1,66 @@
+// src/upstream.rs
+
+use std::sync::{Arc, Mutex, RwLock};
+use reqwest::Client;
+use serde_json::json;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+use crate::config_manager::Config;
+use crate::server::local_metrics;
+use crate::system_info::SystemData;
+
+const DEFAULT_PUSH_INTERVAL: u64 = 10;
+
+/// Push mode: POSTs this pylon's metrics to /api/ingest on the `upstream`
+/// pylon every `upstream_interval` seconds, for pylons the upstream cannot
+/// poll (e.g. behind NAT). Does nothing while no upstream is configured.
+pub async fn push_to_upstream(
+    config_arc: Arc<RwLock<Config>>,
+    system_data: Arc<Mutex<SystemData>>,
+    // The port the server is bound to, which may differ from `local_port`.
+    port: u16,
+    mut shutdown: watch::Receiver<bool>
+) {
+    let client = Client::new();
+    loop {
+        let (upstream, payload, interval) = {
+            // Cloned so the config lock is not held while taking the SystemData lock.
+            let config = config_arc.read().unwrap().clone();
+            let interval = config.upstream_interval.unwrap_or(DEFAULT_PUSH_INTERVAL).max(1);
+            let upstream = config.upstream.clone().map(|url| (url, config.upstream_token.clone().unwrap_or_default()));
+            let payload = upstream.as_ref().map(|_| {
+                let mut payload = local_metrics(&system_data.lock().unwrap(), &config);
+                // The upstream keys us by our address and this port.
+                payload["port"] = json!(port);
+                payload["push_interval"] = json!(interval);
+                payload
+            });
+            (upstream, payload, interval)
+        };
+
+        if let (Some((url, token)), Some(payload)) = (upstream, payload) {
+            let url = format!("{}/api/ingest", url.trim_end_matches('/'));
+            let result = client.post(&url)
+                .bearer_auth(token)
+                .json(&payload)
+                .timeout(Duration::from_secs(10))
+                .send()
+                .await;
+            match result {
+                Ok(resp) if resp.status().is_success() => {},
+                Ok(resp) => println!("Upstream {} rejected pushed metrics: HTTP {}", url, resp.status()),
+                Err(e) => println!("Error pushing metrics to upstream {}: {}", url, e),
+            }
+        }
+
+        tokio::select! {
+            _ = shutdown.changed() => {
+                if *shutdown.borrow() {
+                    println!("Shutting down upstream push.");
+                    break;
+                }
+            },
+            _ = sleep(Duration::from_secs(interval)) => {}
+        }
+    }
+}
@@ -81,7 +81,321 @@ export function initAdminContent() {
       }
     });
   }
+  const silenceForm = document.getElementById('silenceForm');
+  if (silenceForm) {
+    silenceForm.addEventListener('submit', async function(e) {
+      e.preventDefault();
+      const rule = document.getElementById('silenceRule').value;
+      const pylon = document.getElementById('silencePylon').value;
+      const duration = document.getElementById('silenceDuration').value;
+      const comment = document.getElementById('silenceComment').value;
+      try {
+        await postJson('/api/admin/silences', {
+          rule: rule || null,
+          pylon: pylon || null,
+          duration,
+          comment: comment || null
+        });
+        silenceForm.reset();
+        fetchSilences();
+      } catch (err) {
+        console.error("Error adding silence:", err);
+      }
+    });
+  }
+  const pairingCodeButton = document.getElementById('pairingCodeButton');
+  if (pairingCodeButton) {
+    pairingCodeButton.addEventListener('click', async function() {
+      await postJson('/api/admin/pairing/code', {});
+      fetchPairing();
+    });
+  }
+  const pairingForm = document.getElementById('pairingForm');
+  if (pairingForm) {
+    pairingForm.addEventListener('submit', async function(e) {
+      e.preventDefault();
+      const address = document.getElementById('pairingAddress').value;
+      const code = document.getElementById('pairingCode').value;
+      try {
+        const response = await postJson('/api/admin/pairing/join', { address, code });
+        if (!response.ok) {
+          const body = await response.json();
+          alert(`Pairing failed: ${body.error}`);
+          return;
+        }
+        pairingForm.reset();
+        fetchPairing();
+        fetchPylonConfig();
+      } catch (err) {
+        console.error("Error pairing:", err);
+      }
+    });
+  }
   fetchPylonConfig();
+  fetchLogWatch();
+  setInterval(fetchLogWatch, 10000);
+  fetchSilences();
+  setInterval(fetchSilences, 10000);
+  fetchDiscovery();
+  setInterval(fetchDiscovery, 10000);
+  fetchLanPylons();
+  setInterval(fetchLanPylons, 10000);
+  fetchPairing();
+  setInterval(fetchPairing, 10000);
+}
+
+async function postJson(url, body) {
+  return fetch(url, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify(body)
+  });
+}
+
+/**
+ * Lists the active alerts (with an Ack button) and the active silences
+ * (with a Remove button).
+ */
+async function fetchSilences() {
+  const ackList = document.getElementById('ackList');
+  const silenceList = document.getElementById('silenceList');
+  if (!ackList || !silenceList) {
+    return;
+  }
+  try {
+    const response = await fetch('/api/alerts');
+    const alerts = await response.json();
+    ackList.innerHTML = "";
+    alerts.active.forEach(alert => {
+      const li = document.createElement('li');
+      li.innerText = `${alert.id} [${alert.state}] ${alert.message}`;
+      if (alert.acknowledged_at) {
+        li.innerText += ' ✔️ acknowledged';
+      } else {
+        const ackBtn = document.createElement('button');
+        ackBtn.innerText = "Ack";
+        ackBtn.onclick = async function() {
+          await postJson('/api/admin/alerts/ack', { id: alert.id });
+          fetchSilences();
+        };
+        li.appendChild(ackBtn);
+      }
+      ackList.appendChild(li);
+    });
+    silenceList.innerHTML = "";
+    alerts.silences.forEach(silence => {
+      const li = document.createElement('li');
+      const matchers = [silence.pylon && `pylon=${silence.pylon}`, silence.rule && `rule=${silence.rule}`,
+        ...Object.entries(silence.labels || {}).map(([key, value]) => `${key}=${value}`)].filter(Boolean);
+      li.innerText = `${matchers.join(', ') || 'all alerts'} until ${new Date(silence.until).toLocaleString()}`
+        + (silence.comment ? ` (${silence.comment})` : '');
+      const removeBtn = document.createElement('button');
+      removeBtn.innerText = "Remove";
+      removeBtn.onclick = async function() {
+        await postJson('/api/admin/silences/remove', { id: silence.id });
+        fetchSilences();
+      };
+      li.appendChild(removeBtn);
+      silenceList.appendChild(li);
+    });
+  } catch (err) {
+    console.error("Error fetching silences:", err);
+  }
+}
+
+/**
+ * Shows the outstanding pairing codes and the paired pylons (with a Revoke
+ * button for their credential).
+ */
+async function fetchPairing() {
+  const codesDiv = document.getElementById('pairingCodes');
+  const pairedList = document.getElementById('pairedList');
+  if (!codesDiv || !pairedList) {
+    return;
+  }
+  try {
+    const response = await fetch('/api/admin/pairing');
+    if (!response.ok) {
+      return;
+    }
+    const pairing = await response.json();
+    codesDiv.innerText = pairing.codes
+      .map(code => `Code ${code.code} (valid until ${new Date(code.expires_at).toLocaleTimeString()})`)
+      .join('\n');
+    pairedList.innerHTML = "";
+    pairing.peers.forEach(peer => {
+      const li = document.createElement('li');
+      li.innerText = `${peer.name || "No Name"} at ${peer.address}, paired ${new Date(peer.created_at).toLocaleString()}`;
+      if (peer.revoked_at) {
+        li.innerText += ` — revoked ${new Date(peer.revoked_at).toLocaleString()}`;
+      } else {
+        const revokeBtn = document.createElement('button');
+        revokeBtn.innerText = "Revoke";
+        revokeBtn.onclick = async function() {
+          await postJson('/api/admin/pairing/revoke', { id: peer.id });
+          fetchPairing();
+        };
+        li.appendChild(revokeBtn);
+      }
+      pairedList.appendChild(li);
+    });
+  } catch (err) {
+    console.error("Error fetching pairing:", err);
+  }
+}
+
+/**
+ * Lists the pylons heard on the local network that are not configured yet,
+ * each with an Adopt button. The token field is only needed when the two
+ * pylons do not share a lan_secret.
+ */
+async function fetchLanPylons() {
+  const statusDiv = document.getElementById('lanStatus');
+  const list = document.getElementById('lanPylonList');
+  if (!statusDiv || !list) {
+    return;
+  }
+  // Do not wipe a token the admin is typing.
+  if (list.contains(document.activeElement)) {
+    return;
+  }
+  try {
+    const response = await fetch('/api/admin/lan');
+    if (!response.ok) {
+      return;
+    }
+    const lan = await response.json();
+    statusDiv.innerText = lan.enabled
+      ? (lan.pylons.length === 0 ? "No new pylons found." : "")
+      : "LAN discovery is off (set lan_discovery = true).";
+    list.innerHTML = "";
+    lan.pylons.forEach(pylon => {
+      const li = document.createElement('li');
+      li.innerText = `${pylon.name || pylon.id} ${pylon.location ? '(' + pylon.location + ') ' : ''}at ${pylon.ip}:${pylon.port} v${pylon.version} `;
+      const tokenInput = document.createElement('input');
+      tokenInput.type = 'text';
+      tokenInput.placeholder = lan.adoption ? 'Token (optional)' : 'Token';
+      const adoptBtn = document.createElement('button');
+      adoptBtn.innerText = "Adopt";
+      adoptBtn.onclick = async function() {
+        const result = await postJson('/api/admin/lan/adopt', { id: pylon.id, token: tokenInput.value || null });
+        if (!result.ok) {
+          const body = await result.json();
+          alert(`Could not adopt ${pylon.name || pylon.id}: ${body.error}`);
+        }
+        fetchLanPylons();
+        fetchPylonConfig();
+      };
+      li.appendChild(tokenInput);
+      li.appendChild(adoptBtn);
+      list.appendChild(li);
+    });
+  } catch (err) {
+    console.error("Error fetching LAN pylons:", err);
+  }
+}
+
+/**
+ * Lists the peers advertised by remotes that await approval (with Approve
+ * and Reject buttons) and the rejected ones (with an Unreject button).
+ */
+async function fetchDiscovery() {
+  const modeDiv = document.getElementById('discoveryMode');
+  const pendingList = document.getElementById('pendingPeerList');
+  const rejectedList = document.getElementById('rejectedPeerList');
+  if (!modeDiv || !pendingList || !rejectedList) {
+    return;
+  }
+  // Do not wipe a token the admin is typing.
+  if (pendingList.contains(document.activeElement)) {
+    return;
+  }
+  const button = (label, url, key, tokenInput) => {
+    const btn = document.createElement('button');
+    btn.innerText = label;
+    btn.onclick = async function() {
+      const result = await postJson(url, { key, token: tokenInput ? tokenInput.value || null : null });
+      if (!result.ok) {
+        const body = await result.json();
+        alert(`Could not ${label.toLowerCase()} ${key}: ${body.error}`);
+      }
+      fetchDiscovery();
+      fetchPylonConfig();
+    };
+    return btn;
+  };
+  try {
+    const response = await fetch('/api/admin/discovery');
+    if (!response.ok) {
+      return;
+    }
+    const discovery = await response.json();
+    modeDiv.innerText = `Discovery mode: ${discovery.mode}`;
+    pendingList.innerHTML = "";
+    discovery.pending.forEach(peer => {
+      const li = document.createElement('li');
+      li.innerText = `${peer.key} (${peer.remote.name || "No Name"}) via ${peer.via}, last advertised ${new Date(peer.last_seen).toLocaleString()}`;
+      // Adverts carry no token; enter the peer's token (or a credential it issued) to poll it.
+      // Peers offered by pairing or adoption already come with a credential.
+      const tokenInput = document.createElement('input');
+      tokenInput.type = 'text';
+      tokenInput.placeholder = peer.has_token ? 'Token (optional)' : 'Token';
+      li.appendChild(tokenInput);
+      li.appendChild(button("Approve", '/api/admin/discovery/approve', peer.key, tokenInput));
+      li.appendChild(button("Reject", '/api/admin/discovery/reject', peer.key));
+      pendingList.appendChild(li);
+    });
+    rejectedList.innerHTML = "";
+    discovery.rejected.forEach(key => {
+      const li = document.createElement('li');
+      li.innerText = key;
+      li.appendChild(button("Unreject", '/api/admin/discovery/unreject', key));
+      rejectedList.appendChild(li);
+    });
+  } catch (err) {
+    console.error("Error fetching discovered peers:", err);
+  }
+}
+
+/**
+ * Fetches the watched log files and shows their counters and recent matching lines.
+ */
+async function fetchLogWatch() {
+  const container = document.getElementById('logWatchDetails');
+  if (!container) {
+    return;
+  }
+  try {
+    const response = await fetch('/api/admin/logs');
+    if (!response.ok) {
+      return;
+    }
+    const logs = await response.json();
+    container.innerHTML = "";
+    if (logs.length === 0) {
+      container.innerText = "No log files configured.";
+      return;
+    }
+    logs.forEach(log => {
+      const header = document.createElement('h3');
+      header.innerText = log.error ? `${log.path} (⚠️ ${log.error})` : log.path;
+      container.appendChild(header);
+      log.counters.forEach(counter => {
+        const lastMinute = counter.per_minute[counter.per_minute.length - 1] || 0;
+        const line = document.createElement('div');
+        line.innerText = `${counter.pattern}: ${lastMinute}/min, ${counter.total} total`;
+        container.appendChild(line);
+      });
+      if (log.recent_lines.length > 0) {
+        const pre = document.createElement('pre');
+        pre.className = "log-lines";
+        pre.innerText = log.recent_lines.slice(-10).map(l => l.line).join('\n');
+        container.appendChild(pre);
+      }
+    });
+  } catch (err) {
+    console.error("Error fetching log watch:", err);
+  }
 }
 
 /**
@@ -97,7 +411,7 @@ async function fetchPylonConfig() {
       if (Array.isArray(pylons)) {
         pylons.forEach(pylon => {
           const li = document.createElement('li');
-          li.innerText = `${pylon.ip}:${pylon.port} (${pylon.name || "No Name"})`;
+          li.innerText = `${pylon.ip}:${pylon.port} (${pylon.name || "No Name"})` + (pylon.discovered ? ' 🔭 discovered' : '');
           const removeBtn = document.createElement('button');
           removeBtn.innerText = "Remove";
           removeBtn.onclick = async function() {
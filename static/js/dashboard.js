@@ -120,6 +120,33 @@ export function updateNetworkChart(networkChart, startTime, maxDataPoints, throu
   networkChart.update();
 }
 
+/**
+ * Prefills the network chart with recent samples from /api/history,
+ * so the chart is not empty after a page reload.
+ */
+export async function prefillNetworkChart(networkChart, startTime, maxDataPoints) {
+  try {
+    const now = Math.floor(Date.now() / 1000);
+    const from = now - maxDataPoints;
+    const [received, transmitted] = await Promise.all(
+      ['network_received', 'network_transmitted'].map(async metric => {
+        const response = await fetch(`/api/history?metric=${metric}&from=${from}&to=${now}`);
+        return response.ok ? (await response.json()).points : [];
+      })
+    );
+    const startSec = Math.floor(startTime / 1000);
+    received.forEach((point, i) => {
+      const sent = transmitted[i] ? transmitted[i].value : 0;
+      networkChart.data.labels.push(point.ts - startSec);
+      networkChart.data.datasets[0].data.push((point.value / 1024).toFixed(2));
+      networkChart.data.datasets[1].data.push((sent / 1024).toFixed(2));
+    });
+    networkChart.update();
+  } catch (err) {
+    console.error('Error fetching network history:', err);
+  }
+}
+
 /**
  * Fetches local metrics from the server and calls the provided callbacks
  * to update gauges and additional dashboard elements.
@@ -127,6 +154,10 @@ export function updateNetworkChart(networkChart, startTime, maxDataPoints, throu
 export async function fetchLocalMetrics(updateGauges, updateAdditionalElements) {
   try {
     const response = await fetch('/api/metrics');
+    // Until the dashboard is unlocked with the admin key.
+    if (!response.ok) {
+      return;
+    }
     const data = await response.json();
 
     // Update the header text
@@ -155,9 +186,44 @@ export async function fetchRemoteMetrics(updateRemoteGauges) {
       setTimeout(() => { indicator.innerHTML = ''; }, 1500);
     }
     const response = await fetch('/api/remotes');
+    if (!response.ok) {
+      return;
+    }
     const remotes = await response.json();
     updateRemoteGauges(remotes);
   } catch (err) {
     console.error('Error fetching remote metrics:', err);
   }
 }
+
+/**
+ * Fetches the active and recently resolved alerts and passes them to the callback.
+ */
+export async function fetchAlerts(updateAlerts) {
+  try {
+    const response = await fetch('/api/alerts');
+    if (!response.ok) {
+      return;
+    }
+    const alerts = await response.json();
+    updateAlerts(alerts);
+  } catch (err) {
+    console.error('Error fetching alerts:', err);
+  }
+}
+
+/**
+ * Fetches the federation tree (this pylon, its remotes and theirs).
+ */
+export async function fetchFederation(updateFederation) {
+  try {
+    const response = await fetch('/api/federate');
+    if (!response.ok) {
+      return;
+    }
+    const tree = await response.json();
+    updateFederation(tree);
+  } catch (err) {
+    console.error('Error fetching federation tree:', err);
+  }
+}
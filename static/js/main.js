@@ -2,7 +2,7 @@
 
 import { showModal, hideModal } from './modal.js';
 import { loginAdmin, loadAdminContent, initAdminContent } from './admin.js';
-import { initGauges, updateNetworkChart, fetchLocalMetrics, fetchRemoteMetrics } from './dashboard.js';
+import { initGauges, updateNetworkChart, prefillNetworkChart, fetchLocalMetrics, fetchRemoteMetrics, fetchAlerts, fetchFederation } from './dashboard.js';
 
 document.addEventListener('DOMContentLoaded', async function() {
   // ----- Modal Close Events -----
@@ -56,6 +56,7 @@ document.addEventListener('DOMContentLoaded', async function() {
   const maxDataPoints = 30;
   let previousReceived = null;
   let previousTransmitted = null;
+  await prefillNetworkChart(networkChart, startTime, maxDataPoints);
   
   function updateGaugesCallback(data) {
     cpuGauge.animate(data.polled.cpu_usage / 100);
@@ -133,15 +134,15 @@ function updateAdditionalElements(data) {
     const usedDisk = totalDisk > 0 ? (totalDisk - data.polled.disk_free) : 0;
     const totalDiskGB = (totalDisk / (1024 * 1024 * 1024)).toFixed(2);
     const usedDiskGB = (usedDisk / (1024 * 1024 * 1024)).toFixed(2);
+    // One row per configured [software] probe, sorted by name.
+    const software = data.cached.software || {};
+    const softwareRows = Object.keys(software).sort().map(name =>
+      `<tr><td>📦 ${name}</td><td>${software[name]}</td></tr>`
+    ).join('');
     const detailsHTML = `<table>
         <tr><th>Property</th><th>Value</th></tr>
         <tr><td>🌟 OS Version</td><td>${data.cached.os_version}</td></tr>
-        <tr><td>⚙️ Apache Version</td><td>${data.cached.apache_version}</td></tr>
-        <tr><td>🐘 PHP Version</td><td>${data.cached.php_version}</td></tr>
-        <tr><td>💽 MariaDB Version</td><td>${data.cached.mariadb_version}</td></tr>
-        <tr><td>🦀 Rust Version</td><td>${data.cached.rust_version}</td></tr>
-        <tr><td>📟 Node Version</td><td>${data.cached.node_version}</td></tr>
-        <tr><td>📦 npm Version</td><td>${data.cached.npm_version}</td></tr>
+        ${softwareRows}
         <tr><td>🚀 Pylon Version</td><td>${data.version}</td></tr>
         <tr><td>🔧 Processor</td><td>${data.cached.processor}</td></tr>
         <tr><td>💾 Total RAM</td><td>${totalRamMB} MB</td></tr>
@@ -152,6 +153,51 @@ function updateAdditionalElements(data) {
     systemDetails.innerHTML = detailsHTML;
   }
   
+  // Update the Listening Ports Table and TCP State Counts
+  const listeningPortsTable = document.getElementById('listeningPortsTable');
+  if (listeningPortsTable && data.sockets) {
+    const tbody = listeningPortsTable.getElementsByTagName('tbody')[0];
+    tbody.innerHTML = "";
+    data.sockets.listening.forEach(socket => {
+      const row = document.createElement('tr');
+      [socket.protocol, socket.address, socket.port, socket.pid ?? '-', socket.process ?? '-'].forEach(value => {
+        const cell = document.createElement('td');
+        cell.innerText = value;
+        row.appendChild(cell);
+      });
+      tbody.appendChild(row);
+    });
+    const tcpStates = document.getElementById('tcpStates');
+    if (tcpStates) {
+      tcpStates.innerText = Object.entries(data.sockets.tcp_states)
+        .map(([state, count]) => `${state}: ${count}`)
+        .join(' · ');
+    }
+  }
+
+  // Update the TLS Certificates Table
+  const certificatesTable = document.getElementById('certificatesTable');
+  if (certificatesTable && data.certificates) {
+    const tbody = certificatesTable.getElementsByTagName('tbody')[0];
+    tbody.innerHTML = "";
+    data.certificates.forEach(cert => {
+      const row = document.createElement('tr');
+      const cells = cert.error
+        ? [cert.source, cert.error, '', '']
+        : [cert.source, [cert.subject, ...cert.sans.filter(san => san !== cert.subject)].join(', '),
+           cert.issuer, `${cert.days_until_expiry} days`];
+      cells.forEach(value => {
+        const cell = document.createElement('td');
+        cell.innerText = value;
+        row.appendChild(cell);
+      });
+      if (cert.error || cert.days_until_expiry < 14) {
+        row.style.color = cert.error || cert.days_until_expiry < 0 ? 'red' : 'orange';
+      }
+      tbody.appendChild(row);
+    });
+  }
+
   // Update the Top Processes Table
   const topProcessesTableBody = document.getElementById('topProcessesTable')
     ? document.getElementById('topProcessesTable').getElementsByTagName('tbody')[0]
@@ -187,7 +233,7 @@ function updateAdditionalElements(data) {
         light.style.backgroundColor = service.running ? "limegreen" : "red";
         light.style.boxShadow = service.running ? "0 0 10px limegreen" : "0 0 10px red";
         const label = document.createElement('div');
-        label.innerText = service.name;
+        label.innerText = service.flapping ? `${service.name} 〰️` : service.name;
         serviceDiv.appendChild(light);
         serviceDiv.appendChild(label);
         servicesDiv.appendChild(serviceDiv);
@@ -201,8 +247,18 @@ function updateAdditionalElements(data) {
   // ---- Updated: Remote Gauges Callback with full details and link ----
   function updateRemoteGaugesCallback(remotes) {
     window.remoteGauges = window.remoteGauges || {};
+    // Keyed by pylon id when known, so a remote that changes address keeps its block.
+    const keyOf = remote => remote.id ? remote.id : remote.ip.replace(/\./g, '_') + "_" + remote.port;
+    // Drop the blocks of remotes the server no longer reports.
+    const current = new Set(remotes.map(remote => 'remote_' + keyOf(remote)));
+    document.querySelectorAll('#remoteContainer > [id^="remote_"]').forEach(block => {
+      if (!current.has(block.id)) {
+        delete window.remoteGauges[block.id.slice('remote_'.length)];
+        block.remove();
+      }
+    });
     remotes.forEach(remote => {
-      const safeKey = remote.ip.replace(/\./g, '_') + "_" + remote.port;
+      const safeKey = keyOf(remote);
       let remoteBlock = document.getElementById('remote_' + safeKey);
       if (!remoteBlock) {
         remoteBlock = document.createElement('div');
@@ -305,6 +361,20 @@ function updateAdditionalElements(data) {
         remoteServices.style.gap = '10px';
         remoteServices.style.marginTop = '10px';
         remoteBlock.appendChild(remoteServices);
+
+        // Remote Package Updates Line
+        const remotePackages = document.createElement('div');
+        remotePackages.id = 'remotePackages_' + safeKey;
+        remotePackages.style.textAlign = 'center';
+        remotePackages.style.marginTop = '10px';
+        remoteBlock.appendChild(remotePackages);
+
+        // Remote Poll Health Line (failed polls and flapping)
+        const remoteHealth = document.createElement('div');
+        remoteHealth.id = 'remoteHealth_' + safeKey;
+        remoteHealth.style.textAlign = 'center';
+        remoteHealth.style.color = 'orange';
+        remoteBlock.appendChild(remoteHealth);
   
         document.getElementById('remoteContainer').appendChild(remoteBlock);
   
@@ -402,24 +472,147 @@ function updateAdditionalElements(data) {
               light.style.backgroundColor = service.running ? "limegreen" : "red";
               light.style.boxShadow = service.running ? "0 0 10px limegreen" : "0 0 10px red";
               const label = document.createElement('div');
-              label.innerText = service.name;
+              label.innerText = service.flapping ? `${service.name} 〰️` : service.name;
               serviceDiv.appendChild(light);
               serviceDiv.appendChild(label);
               remoteServicesDiv.appendChild(serviceDiv);
             });
           }
         }
+        const remotePackagesDiv = document.getElementById('remotePackages_' + safeKey);
+        const packages = remote.data.packages;
+        if (remotePackagesDiv && packages && packages.checked_at) {
+          let text = `📦 ${packages.upgradable} updates (${packages.security_updates} security)`;
+          if (packages.reboot_required) {
+            text += ' — 🔄 Reboot required';
+            if (packages.reboot_packages.length > 0) {
+              text += ` (${packages.reboot_packages.join(', ')})`;
+            }
+          }
+          remotePackagesDiv.innerText = text;
+          remotePackagesDiv.style.color = packages.security_updates > 0 || packages.reboot_required ? 'orange' : '';
+        }
+        const remoteHealthDiv = document.getElementById('remoteHealth_' + safeKey);
+        if (remoteHealthDiv) {
+          const skew = remote.clock_skew_secs;
+          remoteHealthDiv.innerText = [
+            remote.removed_at ? '🗑️ Removed from config' : '',
+            remote.pushed ? '📤 Pushed' : '',
+            remote.latency_ms != null ? `⏱️ ${remote.latency_ms} ms` : '',
+            skew != null && Math.abs(skew) >= 2 ? `🕒 Clock ${skew > 0 ? '+' : ''}${skew.toFixed(1)}s` : '',
+            remote.consecutive_failures > 0 && remote.last_error
+              ? `⚠️ ${remote.consecutive_failures} failed poll(s): ${describePollError(remote.last_error)}` : '',
+            remote.flapping ? `〰️ Flapping (${remote.transitions} state changes)` : ''
+          ].filter(Boolean).join(' · ');
+        }
       } else {
         const displayName = remote.name || (remote.ip + ':' + remote.port);
         if (remoteBlock) {
           remoteBlock.innerHTML = `<div style="font-size:1.5rem; text-align:center;">
               ${displayName}<br><span class="pulse" style="color:red;">💻❌</span>
+              <div style="font-size:0.9rem; color:orange;">${remote.removed_at ? '🗑️ Removed from config' : remote.last_error ? describePollError(remote.last_error) : ''}</div>
               </div>`;
         }
       }
+      if (remoteBlock) {
+        remoteBlock.style.opacity = remote.removed_at ? '0.5' : '';
+      }
     });
   }
   
+  // Human-readable reason for a failed remote poll.
+  function describePollError(error) {
+    const reasons = {
+      dns: 'DNS lookup failed',
+      connection_refused: 'Connection refused',
+      connect: 'Cannot connect',
+      timeout: 'Timed out',
+      tls: 'TLS error',
+      http_status: 'HTTP error',
+      bad_json: 'Invalid response',
+      auth: 'Authentication failed',
+      stale: 'No recent push'
+    };
+    const reason = reasons[error.kind] || error.kind;
+    return error.kind === 'http_status' ? `${reason} (${error.message})` : reason;
+  }
+
+  // ---- Alerts Card: firing/pending alerts first, then recently resolved ----
+  function updateAlertsCallback(alerts) {
+    const alertsList = document.getElementById('alertsList');
+    if (!alertsList) return;
+    alertsList.innerHTML = "";
+    if (alerts.maintenance.length > 0 || alerts.silences.length > 0) {
+      const notice = document.createElement('div');
+      notice.innerText = [
+        alerts.maintenance.length > 0 ? `🛠️ Maintenance: ${alerts.maintenance.join(', ')}` : '',
+        alerts.silences.length > 0 ? `🔕 ${alerts.silences.length} active silence(s)` : ''
+      ].filter(Boolean).join(' · ');
+      alertsList.appendChild(notice);
+    }
+    const entries = [...alerts.active, ...alerts.recent.slice(0, 5)];
+    if (entries.length === 0) {
+      alertsList.appendChild(document.createTextNode("No alerts ✅"));
+      return;
+    }
+    const colors = { firing: 'red', pending: 'orange', resolved: 'limegreen' };
+    entries.forEach(alert => {
+      const row = document.createElement('div');
+      const since = alert.resolved_at || alert.fired_at || alert.pending_since;
+      row.innerText = `[${alert.state}] ${alert.rule} (${alert.severity}): ${alert.message} — since ${new Date(since).toLocaleString()}`
+        + (alert.silenced_by ? ' 🔕' : '') + (alert.acknowledged_at ? ' ✔️' : '');
+      row.style.color = colors[alert.state];
+      if (alert.description) {
+        row.title = alert.description;
+      }
+      alertsList.appendChild(row);
+    });
+  }
+
+  // ---- Federation Card: remotes of remotes, as reported by each pylon ----
+  function renderFederationNode(child) {
+    const item = document.createElement('li');
+    const status = child.status;
+    const pylon = child.pylon;
+    const name = status.name || (pylon && pylon.name) || child.key;
+    if (pylon && pylon.loop) {
+      item.innerText = `🔁 ${name} (loops back to ${pylon.name || pylon.id})`;
+      return item;
+    }
+    const metrics = pylon ? pylon.metrics : status.data;
+    const cpu = metrics && metrics.polled ? ` — CPU ${metrics.polled.cpu_usage.toFixed(1)}%` : '';
+    item.innerText = `${status.online ? '🟢' : '🔴'} ${name}${status.location ? ' (' + status.location + ')' : ''}${cpu}`;
+    if (child.error) {
+      item.title = `Tree unavailable: ${child.error}`;
+    }
+    if (pylon) {
+      item.appendChild(renderFederationChildren(pylon));
+    }
+    return item;
+  }
+
+  function renderFederationChildren(node) {
+    const list = document.createElement('ul');
+    (node.children || []).forEach(child => list.appendChild(renderFederationNode(child)));
+    if (node.truncated) {
+      const more = document.createElement('li');
+      more.innerText = '… deeper pylons not shown (federation_max_depth)';
+      list.appendChild(more);
+    }
+    return list;
+  }
+
+  function updateFederationCallback(tree) {
+    const card = document.getElementById('federationCard');
+    const container = document.getElementById('federationTree');
+    if (!card || !container) return;
+    // Only worth showing once some remote has remotes of its own.
+    const nested = (tree.children || []).some(child => child.pylon && ((child.pylon.children || []).length > 0 || child.pylon.loop));
+    card.style.display = nested ? '' : 'none';
+    container.innerHTML = '';
+    container.appendChild(renderFederationChildren(tree));
+  }
+
   async function updateDashboard() {
     await fetchLocalMetrics(updateGaugesCallback, updateAdditionalElements);
     await fetchRemoteMetrics(updateRemoteGaugesCallback);
@@ -427,4 +620,8 @@ function updateAdditionalElements(data) {
   
   setInterval(updateDashboard, 1000);
   updateDashboard();
+  setInterval(() => fetchAlerts(updateAlertsCallback), 5000);
+  fetchAlerts(updateAlertsCallback);
+  setInterval(() => fetchFederation(updateFederationCallback), 30000);
+  fetchFederation(updateFederationCallback);
 });